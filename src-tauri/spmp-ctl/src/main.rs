@@ -0,0 +1,338 @@
+//! Controls the running player from the command line, for window manager
+//! key bindings and status bars such as waybar or polybar.
+
+use serde_json::Value as JsonValue;
+use spmp_ipc::paths::{AppPaths, HOME_ARG};
+use spmp_ipc::protocol::{self as ipc, IpcRequest, RepeatState};
+
+/// Has to match `identifier` in tauri.conf.json, so the app's directories
+/// and with them its socket are found.
+const IDENTIFIER: &str = "com.spotify-mini-player.dev";
+
+const DEFAULT_FORMAT: &str = "{status}: {artist} - {title}";
+
+const USAGE: &str = "\
+usage: spmp-ctl [--home <dir>] <command>
+
+commands:
+  status [--json | --format <template>]
+                      print the current playback. Templates can use {title},
+                      {artist}, {album}, {status}, {progress}, {duration},
+                      {shuffle}, {repeat} and {saved}
+  play-pause          toggle playback
+  next                skip to the next track
+  prev                go back to the previous track
+  like                save or unsave the current track
+  shuffle             toggle shuffle
+  repeat [off|context|track]
+                      set the repeat state, or cycle through them
+  seek <position>     seek to e.g. 90 or 1:30, or by e.g. +10 or -10 seconds
+  volume <percent>    set the volume to e.g. 50, or change it by e.g. +5 or -5";
+
+enum Command {
+    /// Prints the state with `format`, or as JSON without one.
+    Status {
+        format: Option<String>,
+    },
+    Request(IpcRequest),
+}
+
+/// Seconds, or minutes and seconds as `m:ss`.
+fn parse_position(position: &str) -> Result<u64, String> {
+    let invalid = || format!("invalid position `{}`", position);
+    let seconds = match position.split_once(':') {
+        Some((minutes, seconds)) => {
+            let minutes: u64 = minutes.parse().map_err(|_| invalid())?;
+            let seconds: u64 = seconds.parse().map_err(|_| invalid())?;
+            minutes * 60 + seconds
+        }
+        None => position.parse().map_err(|_| invalid())?,
+    };
+    Ok(seconds * 1000)
+}
+
+fn parse_seek(position: &str) -> Result<IpcRequest, String> {
+    if let Some(seconds) = position.strip_prefix('+') {
+        let offset_ms = parse_position(seconds)? as i64;
+        return Ok(IpcRequest::SeekBy { offset_ms });
+    }
+    if let Some(seconds) = position.strip_prefix('-') {
+        let offset_ms = -(parse_position(seconds)? as i64);
+        return Ok(IpcRequest::SeekBy { offset_ms });
+    }
+    Ok(IpcRequest::Seek {
+        position_ms: parse_position(position)?,
+    })
+}
+
+fn parse_volume(volume: &str) -> Result<IpcRequest, String> {
+    let invalid = || format!("invalid volume `{}`", volume);
+    if volume.starts_with('+') || volume.starts_with('-') {
+        let offset_percent = volume.parse().map_err(|_| invalid())?;
+        return Ok(IpcRequest::ChangeVolume { offset_percent });
+    }
+    match volume.parse() {
+        Ok(volume_percent) if volume_percent <= 100 => Ok(IpcRequest::SetVolume { volume_percent }),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_repeat(repeat_state: Option<&str>) -> Result<IpcRequest, String> {
+    let repeat_state = match repeat_state {
+        None => {
+            return Ok(IpcRequest::Control {
+                command: "cycle_repeat_state".into(),
+            })
+        }
+        Some("off") => RepeatState::Off,
+        Some("context") => RepeatState::Context,
+        Some("track") => RepeatState::Track,
+        Some(other) => return Err(format!("invalid repeat state `{}`", other)),
+    };
+    Ok(IpcRequest::SetRepeatState { repeat_state })
+}
+
+fn parse_status(args: &[&str]) -> Result<Command, String> {
+    let format = match args {
+        [] => Some(DEFAULT_FORMAT.to_string()),
+        ["--json"] => None,
+        ["--format", format] => Some(format.to_string()),
+        _ => return Err("status takes either --json or --format <template>".into()),
+    };
+    Ok(Command::Status { format })
+}
+
+/// The arguments without [`HOME_ARG`], which [`AppPaths::resolve`] reads.
+fn command_args(args: &[String]) -> Vec<&str> {
+    let mut command_args = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == HOME_ARG {
+            args.next();
+        } else if !arg.starts_with(&format!("{}=", HOME_ARG)) {
+            command_args.push(arg.as_str());
+        }
+    }
+    command_args
+}
+
+fn parse(args: &[&str]) -> Result<Command, String> {
+    let control = |command: &str| {
+        Ok(Command::Request(IpcRequest::Control {
+            command: command.into(),
+        }))
+    };
+    match args {
+        ["status", rest @ ..] => parse_status(rest),
+        ["play-pause"] => control("play_pause"),
+        ["next"] => control("next_track"),
+        ["prev"] => control("prev_track"),
+        ["like"] => control("toggle_saved"),
+        ["shuffle"] => control("toggle_shuffle"),
+        ["repeat"] => parse_repeat(None).map(Command::Request),
+        ["repeat", repeat_state] => parse_repeat(Some(repeat_state)).map(Command::Request),
+        ["seek", position] => parse_seek(position).map(Command::Request),
+        ["volume", volume] => parse_volume(volume).map(Command::Request),
+        _ => Err(USAGE.into()),
+    }
+}
+
+fn format_duration(ms: u64) -> String {
+    let seconds = ms / 1000;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Fills `template` from the serialized `AppState`. Placeholders of an item
+/// are left empty while nothing is playing.
+fn render(template: &str, state: &JsonValue) -> String {
+    let curr = &state["curr"];
+    let str_of = |value: &JsonValue| value.as_str().unwrap_or_default().to_string();
+    let artists: Vec<String> = curr["artists"]
+        .as_array()
+        .map(|artists| {
+            artists
+                .iter()
+                .map(|artist| str_of(&artist["name"]))
+                .collect()
+        })
+        .unwrap_or_default();
+    let status = match (curr.is_null(), state["playing"].as_bool()) {
+        (true, _) => "stopped",
+        (false, Some(true)) => "playing",
+        (false, _) => "paused",
+    };
+
+    let fields = [
+        ("title", str_of(&curr["name"])),
+        ("artist", artists.join(", ")),
+        ("album", str_of(&curr["album"]["name"])),
+        ("status", status.to_string()),
+        (
+            "progress",
+            format_duration(state["progressMs"].as_u64().unwrap_or_default()),
+        ),
+        (
+            "duration",
+            format_duration(curr["durationMs"].as_u64().unwrap_or_default()),
+        ),
+        ("shuffle", state["shuffle"].to_string()),
+        ("repeat", str_of(&state["repeatState"])),
+        (
+            "saved",
+            curr["saved"].as_bool().unwrap_or_default().to_string(),
+        ),
+    ];
+    fields
+        .iter()
+        .fold(template.to_string(), |output, (name, value)| {
+            output.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+fn run(paths: &AppPaths, command: Command) -> Result<(), String> {
+    let (request, status_format) = match command {
+        Command::Status { format } => (IpcRequest::State, Some(format)),
+        Command::Request(request) => (request, None),
+    };
+    let response = ipc::send(paths, &request)
+        .map_err(|error| format!("the player is not running ({})", error))?;
+    if !response.ok {
+        return Err(response.error.unwrap_or_default());
+    }
+
+    if let Some(format) = status_format {
+        let state = response.data.unwrap_or_default();
+        match format {
+            Some(template) => println!("{}", render(&template, &state)),
+            None => println!("{}", state),
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match parse(&command_args(&args)) {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(2);
+        }
+    };
+
+    let result = AppPaths::resolve(IDENTIFIER, &args)
+        .map_err(|error| error.to_string())
+        .and_then(|paths| run(&paths, command));
+    if let Err(error) = result {
+        eprintln!("spmp-ctl: {}", error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn seeks_to_a_position_or_by_an_offset() {
+        assert!(matches!(
+            parse_seek("90"),
+            Ok(IpcRequest::Seek {
+                position_ms: 90_000
+            })
+        ));
+        assert!(matches!(
+            parse_seek("1:30"),
+            Ok(IpcRequest::Seek {
+                position_ms: 90_000
+            })
+        ));
+        assert!(matches!(
+            parse_seek("+10"),
+            Ok(IpcRequest::SeekBy { offset_ms: 10_000 })
+        ));
+        assert!(matches!(
+            parse_seek("-0:10"),
+            Ok(IpcRequest::SeekBy { offset_ms: -10_000 })
+        ));
+        assert!(parse_seek("1:xx").is_err());
+        assert!(parse_seek("soon").is_err());
+    }
+
+    #[test]
+    fn sets_or_changes_the_volume() {
+        assert!(matches!(
+            parse_volume("50"),
+            Ok(IpcRequest::SetVolume { volume_percent: 50 })
+        ));
+        assert!(matches!(
+            parse_volume("+5"),
+            Ok(IpcRequest::ChangeVolume { offset_percent: 5 })
+        ));
+        assert!(matches!(
+            parse_volume("-5"),
+            Ok(IpcRequest::ChangeVolume { offset_percent: -5 })
+        ));
+        assert!(parse_volume("101").is_err());
+        assert!(parse_volume("loud").is_err());
+    }
+
+    #[test]
+    fn sets_or_cycles_the_repeat_state() {
+        assert!(matches!(
+            parse_repeat(Some("track")),
+            Ok(IpcRequest::SetRepeatState {
+                repeat_state: RepeatState::Track
+            })
+        ));
+        assert!(matches!(
+            parse_repeat(None),
+            Ok(IpcRequest::Control { command }) if command == "cycle_repeat_state"
+        ));
+        assert!(parse_repeat(Some("all")).is_err());
+    }
+
+    #[test]
+    fn drops_the_home_flag() {
+        let args: Vec<String> = ["--home", "/tmp/spmp", "seek", "+10", "--home=/tmp"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        assert_eq!(command_args(&args), ["seek", "+10"]);
+    }
+
+    #[test]
+    fn renders_the_playing_item() {
+        let state = json!({
+            "curr": {
+                "name": "Song",
+                "artists": [{ "name": "A" }, { "name": "B" }],
+                "album": { "name": "Album" },
+                "durationMs": 185_000,
+                "saved": true,
+            },
+            "playing": true,
+            "progressMs": 61_500,
+            "shuffle": false,
+            "repeatState": "context",
+        });
+        assert_eq!(
+            render(
+                "{status}: {artist} - {title} ({album}) {progress}/{duration}",
+                &state
+            ),
+            "playing: A, B - Song (Album) 1:01/3:05"
+        );
+        assert_eq!(
+            render("{shuffle} {repeat} {saved} {unknown}", &state),
+            "false context true {unknown}"
+        );
+    }
+
+    #[test]
+    fn renders_nothing_playing() {
+        let state = json!({ "curr": null, "playing": false });
+        assert_eq!(render(DEFAULT_FORMAT, &state), "stopped:  - ");
+    }
+}
@@ -0,0 +1,6 @@
+//! The parts of the player that other processes need too: where its files
+//! live and how to talk to the running instance. Kept free of Tauri, so a
+//! small client like `spmp-ctl` doesn't link the webview stack.
+
+pub mod paths;
+pub mod protocol;
@@ -0,0 +1,239 @@
+use std::path::{Path, PathBuf};
+
+/// Points every directory below one root, e.g. a temp dir, instead of the
+/// platform ones. The flag takes precedence over the env var.
+pub const HOME_ENV_VAR: &str = "SPOTIFY_MINI_PLAYER_HOME";
+pub const HOME_ARG: &str = "--home";
+
+const STORE_FILE_NAME: &str = "store.bin";
+
+/// Where the app keeps its files. On Linux these follow the XDG base
+/// directory spec, elsewhere the platform's equivalents.
+#[derive(Clone, Debug)]
+pub struct AppPaths {
+    /// Settings and the store that holds them.
+    pub config_dir: PathBuf,
+    /// Logs, diagnostics reports and anything else worth keeping.
+    pub data_dir: PathBuf,
+    /// Downloads that can be fetched again, such as covers.
+    pub cache_dir: PathBuf,
+    /// The instance socket and lock. Not cleaned up by the app, so only
+    /// small, short-lived files go here. Only the user may enter it, see
+    /// [`AppPaths::create_runtime_dir`].
+    pub runtime_dir: PathBuf,
+    /// Whether the directories come from [`HOME_ENV_VAR`] or [`HOME_ARG`].
+    pub overridden: bool,
+}
+
+fn home_arg(args: &[String]) -> Option<PathBuf> {
+    let position = args.iter().position(|arg| arg == HOME_ARG);
+    if let Some(dir) = position.and_then(|position| args.get(position + 1)) {
+        return Some(dir.into());
+    }
+    args.iter().find_map(|arg| {
+        arg.strip_prefix(&format!("{}=", HOME_ARG))
+            .map(PathBuf::from)
+    })
+}
+
+impl AppPaths {
+    /// Puts each directory below `root`, which is what the override does.
+    pub fn from_root(root: &Path) -> Self {
+        Self {
+            config_dir: root.join("config"),
+            data_dir: root.join("data"),
+            cache_dir: root.join("cache"),
+            runtime_dir: root.join("run"),
+            overridden: true,
+        }
+    }
+
+    /// Resolves the directories for the app with bundle `identifier`,
+    /// honouring an override in `args` or the environment.
+    pub fn resolve(identifier: &str, args: &[String]) -> anyhow::Result<Self> {
+        let root = home_arg(args).or_else(|| std::env::var_os(HOME_ENV_VAR).map(PathBuf::from));
+        if let Some(root) = root {
+            return Ok(Self::from_root(&root));
+        }
+
+        let dir = |base: Option<PathBuf>, kind: &str| {
+            base.map(|base| base.join(identifier))
+                .ok_or_else(|| anyhow::anyhow!("no {} dir", kind))
+        };
+        Ok(Self {
+            config_dir: dir(dirs_next::config_dir(), "config")?,
+            data_dir: dir(dirs_next::data_dir(), "data")?,
+            cache_dir: dir(dirs_next::cache_dir(), "cache")?,
+            runtime_dir: dirs_next::runtime_dir().unwrap_or_else(private_temp_dir),
+            overridden: false,
+        })
+    }
+
+    /// Creates the runtime dir, or checks that an existing one belongs to the
+    /// user and that nobody else may enter it. Whoever can reach the socket
+    /// can control playback, so it must never live in a shared directory.
+    pub fn create_runtime_dir(&self) -> std::io::Result<()> {
+        create_private_dir(&self.runtime_dir)
+    }
+
+    pub fn store_path(&self) -> PathBuf {
+        self.config_dir.join(STORE_FILE_NAME)
+    }
+
+    pub fn log_dir(&self) -> PathBuf {
+        self.data_dir.join("logs")
+    }
+
+    pub fn covers_dir(&self) -> PathBuf {
+        self.cache_dir.join("covers")
+    }
+
+    /// Older versions kept the store next to the logs in the data dir. It is
+    /// moved over once, unless a store already exists in the config dir.
+    pub fn move_legacy_store(&self) {
+        let legacy_path = self.data_dir.join(STORE_FILE_NAME);
+        let store_path = self.store_path();
+        if self.overridden || legacy_path == store_path || !legacy_path.is_file() {
+            return;
+        }
+        if store_path.exists() {
+            tracing::warn!(legacy = %legacy_path.display(), "ignoring store left in the data dir");
+            return;
+        }
+
+        let result = std::fs::create_dir_all(&self.config_dir)
+            .and_then(|()| std::fs::rename(&legacy_path, &store_path));
+        match result {
+            Ok(()) => tracing::info!(path = %store_path.display(), "moved store to the config dir"),
+            Err(error) => tracing::error!(%error, "failed to move store to the config dir"),
+        }
+    }
+}
+
+/// Where the runtime dir goes without `XDG_RUNTIME_DIR`, e.g. on Linux
+/// without a login session, and on other platforms. The temp dir is shared
+/// with other users, so each user gets a directory of their own in it.
+#[cfg(unix)]
+fn private_temp_dir() -> PathBuf {
+    // SAFETY: `getuid` can't fail and has no preconditions.
+    let uid = unsafe { libc::getuid() };
+    std::env::temp_dir().join(format!("spotify-mini-player-{}", uid))
+}
+
+/// Named pipes don't live in the file system, so only the lock goes here,
+/// and the temp dir is already per user.
+#[cfg(windows)]
+fn private_temp_dir() -> PathBuf {
+    std::env::temp_dir()
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    if let Some(parent) = dir.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => return Ok(()),
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(error) => return Err(error),
+    }
+
+    // Not followed, so a link planted by another user is refused as well.
+    let metadata = std::fs::symlink_metadata(dir)?;
+    // SAFETY: `getuid` can't fail and has no preconditions.
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!("{} is not a directory owned by the user", dir.display()),
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        std::fs::set_permissions(dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn create_private_dir(dir: &Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn reads_the_home_flag() {
+        assert_eq!(
+            home_arg(&args(&["--home", "/tmp/a"])),
+            Some(PathBuf::from("/tmp/a"))
+        );
+        assert_eq!(
+            home_arg(&args(&["--autostart", "--home=/tmp/b"])),
+            Some(PathBuf::from("/tmp/b"))
+        );
+        assert_eq!(home_arg(&args(&["--home"])), None);
+        assert_eq!(home_arg(&args(&["--homework", "x"])), None);
+        assert_eq!(home_arg(&[]), None);
+    }
+
+    #[test]
+    fn the_flag_overrides_every_dir() {
+        let paths = AppPaths::resolve("id", &args(&["--home", "/tmp/root"])).unwrap();
+        assert!(paths.overridden);
+        assert_eq!(paths.store_path(), Path::new("/tmp/root/config/store.bin"));
+        assert_eq!(paths.log_dir(), Path::new("/tmp/root/data/logs"));
+        assert_eq!(paths.covers_dir(), Path::new("/tmp/root/cache/covers"));
+        assert_eq!(paths.runtime_dir, Path::new("/tmp/root/run"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn runtime_dir_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let paths = AppPaths::from_root(root.path());
+        paths.create_runtime_dir().unwrap();
+        let mode = std::fs::metadata(&paths.runtime_dir)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        std::fs::set_permissions(&paths.runtime_dir, std::fs::Permissions::from_mode(0o777))
+            .unwrap();
+        paths.create_runtime_dir().unwrap();
+        let mode = std::fs::metadata(&paths.runtime_dir)
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[test]
+    fn moves_the_legacy_store_once() {
+        let root = tempfile::tempdir().unwrap();
+        let paths = AppPaths {
+            overridden: false,
+            ..AppPaths::from_root(root.path())
+        };
+        std::fs::create_dir_all(&paths.data_dir).unwrap();
+        std::fs::write(paths.data_dir.join(STORE_FILE_NAME), "{}").unwrap();
+
+        paths.move_legacy_store();
+        assert_eq!(std::fs::read_to_string(paths.store_path()).unwrap(), "{}");
+        assert!(!paths.data_dir.join(STORE_FILE_NAME).exists());
+
+        std::fs::write(paths.data_dir.join(STORE_FILE_NAME), "old").unwrap();
+        paths.move_legacy_store();
+        assert_eq!(std::fs::read_to_string(paths.store_path()).unwrap(), "{}");
+    }
+}
@@ -0,0 +1,159 @@
+//! What goes over the instance socket. Kept free of the app's state and of
+//! rspotify, since `spmp-ctl` speaks it as well.
+
+use interprocess::local_socket::LocalSocketStream;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use std::io::{BufRead, BufReader, Write};
+
+use crate::paths::AppPaths;
+
+/// Serialized like rspotify's `RepeatState`, which the app maps it to.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RepeatState {
+    Off,
+    Track,
+    Context,
+}
+
+/// Messages a second process sends to the running instance, one JSON object
+/// per line. Every request gets exactly one [`IpcResponse`] line back.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IpcRequest {
+    /// A second launch hands over its command line and exits.
+    Forward {
+        args: Vec<String>,
+    },
+    /// The current playback, answered with the serialized `AppState`.
+    State,
+    /// A playback control by the name of its Tauri command, e.g. `play_pause`.
+    Control {
+        command: String,
+    },
+    Seek {
+        position_ms: u64,
+    },
+    /// Seeks from the current position, clamped to the track.
+    SeekBy {
+        offset_ms: i64,
+    },
+    SetVolume {
+        volume_percent: u8,
+    },
+    /// Changes the volume from the last known one, clamped to 0..=100.
+    ChangeVolume {
+        offset_percent: i16,
+    },
+    SetRepeatState {
+        repeat_state: RepeatState,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct IpcResponse {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<JsonValue>,
+}
+
+impl IpcResponse {
+    pub fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            data: None,
+        }
+    }
+
+    pub fn data(data: impl Serialize) -> Self {
+        match serde_json::to_value(data) {
+            Ok(data) => Self {
+                data: Some(data),
+                ..Self::ok()
+            },
+            Err(error) => Self::error(error),
+        }
+    }
+
+    pub fn error(error: impl ToString) -> Self {
+        Self {
+            ok: false,
+            error: Some(error.to_string()),
+            data: None,
+        }
+    }
+}
+
+#[cfg(unix)]
+pub fn socket_name(paths: &AppPaths) -> String {
+    paths
+        .runtime_dir
+        .join("spotify-mini-player.sock")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Named pipes live in their own namespace, so a bare name is enough. An
+/// overridden home gets its own pipe, so it runs apart from the usual app.
+#[cfg(windows)]
+pub fn socket_name(paths: &AppPaths) -> String {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    match paths.overridden {
+        true => {
+            let mut hasher = DefaultHasher::new();
+            paths.runtime_dir.hash(&mut hasher);
+            format!("spotify-mini-player-{:x}", hasher.finish())
+        }
+        false => "spotify-mini-player".into(),
+    }
+}
+
+/// Sends one request to the running instance and waits for its response.
+pub fn send(paths: &AppPaths, request: &IpcRequest) -> anyhow::Result<IpcResponse> {
+    let stream = LocalSocketStream::connect(socket_name(paths))?;
+    let mut reader = BufReader::new(stream);
+
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    reader.get_mut().write_all(line.as_bytes())?;
+
+    let mut response = String::new();
+    reader.read_line(&mut response)?;
+    Ok(serde_json::from_str(&response)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_tagged_by_type() {
+        let request = IpcRequest::SetRepeatState {
+            repeat_state: RepeatState::Context,
+        };
+        assert_eq!(
+            serde_json::to_string(&request).unwrap(),
+            r#"{"type":"set_repeat_state","repeat_state":"context"}"#
+        );
+        let request: IpcRequest =
+            serde_json::from_str(r#"{"type":"seek_by","offset_ms":-10000}"#).unwrap();
+        assert!(matches!(request, IpcRequest::SeekBy { offset_ms: -10000 }));
+    }
+
+    #[test]
+    fn responses_skip_empty_fields() {
+        assert_eq!(
+            serde_json::to_string(&IpcResponse::ok()).unwrap(),
+            r#"{"ok":true}"#
+        );
+        let response: IpcResponse = serde_json::from_str(r#"{"ok":false,"error":"no"}"#).unwrap();
+        assert_eq!(response.error.as_deref(), Some("no"));
+        assert!(response.data.is_none());
+    }
+}
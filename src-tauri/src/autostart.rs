@@ -0,0 +1,217 @@
+use rspotify::ClientError;
+use std::time::Duration;
+
+use crate::error_log::{ErrorKind, Severity};
+use crate::handlers::subscribe_to_event_loop;
+use crate::reauth::reauth_spotify;
+
+/// Passed by the login entry, so a launch at login can be told apart from
+/// one by hand.
+pub const AUTOSTART_ARG: &str = "--autostart";
+
+/// The network is often not up yet right after login, so the first reauth
+/// is retried for a while instead of waiting for the first click.
+const LAUNCH_REAUTH_ATTEMPTS: u32 = 6;
+const LAUNCH_REAUTH_BACKOFF: Duration = Duration::from_secs(5);
+
+pub fn launched_at_login() -> bool {
+    std::env::args().any(|arg| arg == AUTOSTART_ARG)
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::path::PathBuf;
+
+    use super::AUTOSTART_ARG;
+    use crate::helpers::{executable, quote_exec_arg};
+
+    fn entry_path(app_handle: &tauri::AppHandle) -> anyhow::Result<PathBuf> {
+        let config_dir =
+            tauri::api::path::config_dir().ok_or_else(|| anyhow::anyhow!("no config dir"))?;
+        let name = &app_handle.package_info().name;
+        Ok(config_dir
+            .join("autostart")
+            .join(format!("{}.desktop", name)))
+    }
+
+    pub fn is_enabled(app_handle: &tauri::AppHandle) -> bool {
+        entry_path(app_handle).map_or(false, |path| path.is_file())
+    }
+
+    pub fn enable(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        let path = entry_path(app_handle)?;
+        let exec = quote_exec_arg(&executable()?.to_string_lossy());
+        let entry = format!(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name={}\n\
+             Exec={} {}\n\
+             Terminal=false\n\
+             X-GNOME-Autostart-enabled=true\n",
+            app_handle.package_info().name,
+            exec,
+            AUTOSTART_ARG,
+        );
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, entry)?;
+        Ok(())
+    }
+
+    pub fn disable(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        let path = entry_path(app_handle)?;
+        if path.is_file() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod imp {
+    use std::path::PathBuf;
+
+    use super::AUTOSTART_ARG;
+    use crate::helpers::executable;
+
+    fn label(app_handle: &tauri::AppHandle) -> String {
+        app_handle.config().tauri.bundle.identifier.clone()
+    }
+
+    fn agent_path(app_handle: &tauri::AppHandle) -> anyhow::Result<PathBuf> {
+        let home_dir =
+            tauri::api::path::home_dir().ok_or_else(|| anyhow::anyhow!("no home dir"))?;
+        Ok(home_dir
+            .join("Library/LaunchAgents")
+            .join(format!("{}.plist", label(app_handle))))
+    }
+
+    fn escape(text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    }
+
+    pub fn is_enabled(app_handle: &tauri::AppHandle) -> bool {
+        agent_path(app_handle).map_or(false, |path| path.is_file())
+    }
+
+    pub fn enable(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        let path = agent_path(app_handle)?;
+        let agent = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>{}</string>
+        <string>{}</string>
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+</dict>
+</plist>
+"#,
+            escape(&label(app_handle)),
+            escape(&executable()?.to_string_lossy()),
+            AUTOSTART_ARG,
+        );
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(path, agent)?;
+        Ok(())
+    }
+
+    pub fn disable(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        let path = agent_path(app_handle)?;
+        if path.is_file() {
+            std::fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use winreg::enums::{HKEY_CURRENT_USER, KEY_QUERY_VALUE, KEY_SET_VALUE};
+    use winreg::RegKey;
+
+    use super::AUTOSTART_ARG;
+    use crate::helpers::executable;
+
+    const RUN_KEY: &str = r"Software\Microsoft\Windows\CurrentVersion\Run";
+
+    fn run_key(flags: u32) -> std::io::Result<RegKey> {
+        RegKey::predef(HKEY_CURRENT_USER).open_subkey_with_flags(RUN_KEY, flags)
+    }
+
+    pub fn is_enabled(app_handle: &tauri::AppHandle) -> bool {
+        run_key(KEY_QUERY_VALUE)
+            .and_then(|key| key.get_value::<String, _>(&app_handle.package_info().name))
+            .is_ok()
+    }
+
+    pub fn enable(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        let command = format!("\"{}\" {}", executable()?.display(), AUTOSTART_ARG);
+        run_key(KEY_SET_VALUE)?.set_value(&app_handle.package_info().name, &command)?;
+        Ok(())
+    }
+
+    pub fn disable(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        if is_enabled(app_handle) {
+            run_key(KEY_SET_VALUE)?.delete_value(&app_handle.package_info().name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether the app is registered to start at login. The OS entry is the
+/// only record of this, so it can't drift from what actually happens.
+pub fn is_enabled(app_handle: &tauri::AppHandle) -> bool {
+    imp::is_enabled(app_handle)
+}
+
+pub fn enable(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+    imp::enable(app_handle)?;
+    tracing::info!("autostart enabled");
+    Ok(())
+}
+
+pub fn disable(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+    imp::disable(app_handle)?;
+    tracing::info!("autostart disabled");
+    Ok(())
+}
+
+fn is_transient(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<ClientError>().map_or(false, |error| {
+        ErrorKind::from(error).severity() == Severity::Warning
+    })
+}
+
+/// Restores the session in the background right after launch, and starts
+/// polling once it succeeds so the player is ready before the first click.
+pub async fn reauth_on_launch(app_handle: tauri::AppHandle) {
+    let attempts = match launched_at_login() {
+        true => LAUNCH_REAUTH_ATTEMPTS,
+        false => 1,
+    };
+
+    for attempt in 1..=attempts {
+        match reauth_spotify(&app_handle).await {
+            Ok(()) => {
+                subscribe_to_event_loop(&app_handle).unwrap();
+                return;
+            }
+            Err(error) if attempt < attempts && is_transient(&error) => {
+                tracing::debug!(%error, attempt, "reauth on launch failed, retrying");
+                tokio::time::sleep(LAUNCH_REAUTH_BACKOFF).await;
+            }
+            Err(error) => {
+                tracing::info!(%error, "no session restored on launch");
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use rspotify::prelude::Id;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
+
+use crate::paths::AppPaths;
+use crate::state::{PlayableId, SimplifiedItem};
+
+/// Covers kept on disk. Older ones are deleted as new ones come in, the
+/// cache would otherwise hold a cover for every item ever played.
+const MAX_CACHED_COVERS: usize = 200;
+
+/// Where the cover of `item` is cached. Covers are keyed by item, so each is
+/// fetched once.
+fn path(app_handle: &tauri::AppHandle, item: &SimplifiedItem) -> Option<PathBuf> {
+    let name = match item.id.as_ref()? {
+        PlayableId::Track(id) => id.id(),
+        PlayableId::Episode(id) => id.id(),
+    };
+    let dir = app_handle.state::<AppPaths>().covers_dir();
+    Some(dir.join(format!("{}.jpg", name)))
+}
+
+/// The cover of `item`, if it was downloaded before.
+pub fn cached(app_handle: &tauri::AppHandle, item: &SimplifiedItem) -> Option<PathBuf> {
+    path(app_handle, item).filter(|path| path.is_file())
+}
+
+/// Downloads the cover into the cache dir, for consumers that only take
+/// local paths, like notification servers.
+pub async fn fetch(app_handle: &tauri::AppHandle, item: &SimplifiedItem) -> Option<PathBuf> {
+    let url = item.image_url.as_ref()?;
+    let path = path(app_handle, item)?;
+    if path.is_file() {
+        return Some(path);
+    }
+
+    let download = async {
+        let bytes = reqwest::get(url).await?.error_for_status()?.bytes().await?;
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, bytes)?;
+        prune(path.parent().unwrap(), MAX_CACHED_COVERS)?;
+        anyhow::Ok(())
+    };
+    match download.await {
+        Ok(()) => Some(path),
+        Err(error) => {
+            tracing::debug!(%error, url, "failed to download cover");
+            None
+        }
+    }
+}
+
+/// Deletes all but the `keep` most recently written files in `dir`.
+fn prune(dir: &Path, keep: usize) -> std::io::Result<()> {
+    let mut covers = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            covers.push((metadata.modified()?, entry.path()));
+        }
+    }
+    if covers.len() <= keep {
+        return Ok(());
+    }
+
+    covers.sort_by(|a, b| b.0.cmp(&a.0));
+    for (_, path) in covers.into_iter().skip(keep) {
+        std::fs::remove_file(path)?;
+    }
+    Ok(())
+}
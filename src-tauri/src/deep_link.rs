@@ -0,0 +1,232 @@
+use tauri::Manager;
+use url::Url;
+
+use crate::autostart::AUTOSTART_ARG;
+use crate::error_log::report;
+use crate::handlers::control::{self, ControlCommand};
+use crate::handlers::{playback, HandlerError};
+use crate::reauth::ensure_token;
+use crate::state::AppStore;
+use crate::window;
+
+pub const SCHEME: &str = "spotify-mini-player";
+
+/// What a `spotify-mini-player://<action>` link asks for.
+#[derive(Debug, PartialEq, Eq)]
+pub enum DeepLink {
+    /// `play`, optionally with a `uri` query such as `spotify:album:<id>`.
+    Play(Option<String>),
+    Pause,
+    PlayPause,
+    Next,
+    Previous,
+    Like,
+    Show,
+}
+
+impl DeepLink {
+    pub fn parse(link: &str) -> Result<Self, String> {
+        let url = Url::parse(link).map_err(|e| e.to_string())?;
+        if url.scheme() != SCHEME {
+            return Err(format!("not a {}:// link", SCHEME));
+        }
+
+        let action = url.host_str().unwrap_or_default();
+        let link = match action {
+            "play" => DeepLink::Play(
+                url.query_pairs()
+                    .find(|(key, _)| key == "uri")
+                    .map(|(_, uri)| uri.into_owned()),
+            ),
+            "pause" => DeepLink::Pause,
+            "play_pause" => DeepLink::PlayPause,
+            "next" => DeepLink::Next,
+            "prev" | "previous" => DeepLink::Previous,
+            "like" => DeepLink::Like,
+            "show" => DeepLink::Show,
+            _ => return Err(format!("unknown action `{}`", action)),
+        };
+        Ok(link)
+    }
+}
+
+/// Runs `link`. Failures are already in the error log.
+async fn run(app_handle: &tauri::AppHandle, link: DeepLink) -> Result<(), HandlerError> {
+    let playing = app_handle.state::<AppStore>().snapshot().playing;
+    let command = match link {
+        DeepLink::Show => {
+            window::show_window(app_handle);
+            return Ok(());
+        }
+        DeepLink::Play(Some(uri)) => {
+            ensure_token(app_handle).await;
+            let app_store = app_handle.state::<AppStore>();
+            let result = playback::play_uri(&app_store, &uri).await;
+            return report(app_handle, "play_uri", result);
+        }
+        DeepLink::Play(None) if !playing => ControlCommand::PlayPause,
+        DeepLink::Pause if playing => ControlCommand::PlayPause,
+        DeepLink::Play(None) | DeepLink::Pause => return Ok(()),
+        DeepLink::PlayPause => ControlCommand::PlayPause,
+        DeepLink::Next => ControlCommand::NextTrack,
+        DeepLink::Previous => ControlCommand::PrevTrack,
+        DeepLink::Like => ControlCommand::ToggleSaved,
+    };
+    control::execute(app_handle, command).await
+}
+
+/// Runs every link in `args`. Returns whether there was any, so a plain
+/// second launch can be told apart from one opened through a link.
+pub async fn handle_links(app_handle: &tauri::AppHandle, args: &[String]) -> bool {
+    let prefix = format!("{}://", SCHEME);
+    let links: Vec<&String> = args.iter().filter(|arg| arg.starts_with(&prefix)).collect();
+
+    for link in &links {
+        match DeepLink::parse(link) {
+            Ok(parsed) => {
+                tracing::info!(%link, "deep link");
+                let _ = run(app_handle, parsed).await;
+            }
+            Err(error) => tracing::warn!(%link, %error, "invalid deep link"),
+        }
+    }
+    !links.is_empty()
+}
+
+/// Handles the command line a second launch forwarded. Launching the app
+/// again by hand opens the player, as if the tray icon had been clicked.
+pub async fn handle_forwarded(app_handle: &tauri::AppHandle, args: &[String]) {
+    let has_links = handle_links(app_handle, args).await;
+    if !has_links && !args.iter().any(|arg| arg == AUTOSTART_ARG) {
+        window::show_window(app_handle);
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use super::SCHEME;
+    use crate::helpers::{executable, quote_exec_arg};
+
+    /// Registers a hidden desktop entry as the handler of the scheme, which
+    /// then starts the app with the link as its argument.
+    pub fn register(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        let data_dir =
+            tauri::api::path::data_dir().ok_or_else(|| anyhow::anyhow!("no data dir"))?;
+        let file_name = format!("{}-handler.desktop", SCHEME);
+        let path = data_dir.join("applications").join(&file_name);
+        let entry = format!(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name={}\n\
+             Exec={} %u\n\
+             Terminal=false\n\
+             NoDisplay=true\n\
+             MimeType=x-scheme-handler/{};\n",
+            app_handle.package_info().name,
+            quote_exec_arg(&executable()?.to_string_lossy()),
+            SCHEME,
+        );
+        if std::fs::read_to_string(&path).ok().as_deref() == Some(entry.as_str()) {
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        std::fs::write(&path, entry)?;
+        let status = std::process::Command::new("xdg-mime")
+            .args(["default", &file_name])
+            .arg(format!("x-scheme-handler/{}", SCHEME))
+            .status()?;
+        anyhow::ensure!(status.success(), "xdg-mime exited with {}", status);
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod imp {
+    use winreg::enums::HKEY_CURRENT_USER;
+    use winreg::RegKey;
+
+    use super::SCHEME;
+    use crate::helpers::executable;
+
+    pub fn register(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        let classes = RegKey::predef(HKEY_CURRENT_USER).open_subkey(r"Software\Classes")?;
+        let (scheme_key, _) = classes.create_subkey(SCHEME)?;
+        scheme_key.set_value("", &format!("URL:{} link", app_handle.package_info().name))?;
+        scheme_key.set_value("URL Protocol", &"")?;
+        let (command_key, _) = scheme_key.create_subkey(r"shell\open\command")?;
+        command_key.set_value("", &format!("\"{}\" \"%1\"", executable()?.display()))?;
+        Ok(())
+    }
+}
+
+/// macOS hands links to a running app as Apple Events rather than on the
+/// command line, which tauri 1 has no hook for, so links aren't supported
+/// there yet.
+#[cfg(target_os = "macos")]
+mod imp {
+    pub fn register(_app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+        anyhow::bail!("links are not supported on macOS")
+    }
+}
+
+/// Makes the OS open `spotify-mini-player://` links with this binary. Done
+/// on every launch, so the entry follows the app when it moves.
+pub fn register(app_handle: &tauri::AppHandle) {
+    if let Err(error) = imp::register(app_handle) {
+        tracing::warn!(%error, "failed to register {}:// links", SCHEME);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_actions() {
+        let cases = [
+            ("spotify-mini-player://play", DeepLink::Play(None)),
+            ("spotify-mini-player://pause", DeepLink::Pause),
+            ("spotify-mini-player://play_pause", DeepLink::PlayPause),
+            ("spotify-mini-player://next", DeepLink::Next),
+            ("spotify-mini-player://prev", DeepLink::Previous),
+            ("spotify-mini-player://previous", DeepLink::Previous),
+            ("spotify-mini-player://like", DeepLink::Like),
+            ("spotify-mini-player://show", DeepLink::Show),
+            ("spotify-mini-player://next/", DeepLink::Next),
+        ];
+        for (link, expected) in cases {
+            assert_eq!(DeepLink::parse(link), Ok(expected), "{}", link);
+        }
+    }
+
+    #[test]
+    fn parses_the_uri_to_play() {
+        assert_eq!(
+            DeepLink::parse("spotify-mini-player://play?uri=spotify:album:0sNOF9WDwhWunNAHPD3Baj"),
+            Ok(DeepLink::Play(Some(
+                "spotify:album:0sNOF9WDwhWunNAHPD3Baj".into()
+            )))
+        );
+        assert_eq!(
+            DeepLink::parse(
+                "spotify-mini-player://play?uri=spotify%3Atrack%3A4iV5W9uYEdYUVa79Axb7Rh"
+            ),
+            Ok(DeepLink::Play(Some(
+                "spotify:track:4iV5W9uYEdYUVa79Axb7Rh".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn rejects_other_links() {
+        for link in [
+            "spotify-mini-player://rewind",
+            "https://play",
+            "spotify:track:4iV5W9uYEdYUVa79Axb7Rh",
+            "not a link",
+        ] {
+            assert!(DeepLink::parse(link).is_err(), "{}", link);
+        }
+    }
+}
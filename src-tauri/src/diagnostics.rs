@@ -0,0 +1,172 @@
+use serde::Serialize;
+use std::net::TcpListener;
+use std::path::PathBuf;
+use tauri::Manager;
+
+use crate::error_log::{ErrorLog, PlaybackError};
+use crate::handlers::playback_call_buffer;
+use crate::paths::{self, AppPaths};
+use crate::scopes::get_scopes;
+use crate::settings;
+use crate::spotify_request;
+use crate::state::{AppStore, EventLoopHandle};
+
+const CREDENTIAL_ENV_VARS: [&str; 3] = [
+    "RSPOTIFY_CLIENT_ID",
+    "RSPOTIFY_CLIENT_SECRET",
+    "RSPOTIFY_REDIRECT_URI",
+];
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenDiagnostics {
+    pub present: bool,
+    pub has_refresh_token: bool,
+    pub expires_at_ms: Option<i64>,
+    pub expired: bool,
+}
+
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialSource {
+    EnvFile,
+    Environment,
+    Missing,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialDiagnostics {
+    pub variable: &'static str,
+    pub source: CredentialSource,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitDiagnostics {
+    pub limited_until_ms: Option<i64>,
+    pub hits: u64,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Diagnostics {
+    pub app_version: String,
+    pub os: &'static str,
+    pub generated_at_ms: i64,
+    pub token: TokenDiagnostics,
+    pub granted_scopes: Vec<String>,
+    pub missing_scopes: Vec<String>,
+    pub last_successful_poll_ms: Option<i64>,
+    pub poll_cadence_ms: u64,
+    pub event_loop_running: bool,
+    pub rate_limit: RateLimitDiagnostics,
+    pub callback_port: u16,
+    pub callback_port_free: bool,
+    pub env_file: Option<PathBuf>,
+    pub credentials: Vec<CredentialDiagnostics>,
+    pub store_path: PathBuf,
+    pub recent_errors: Vec<PlaybackError>,
+}
+
+fn env_file() -> Option<PathBuf> {
+    let path = std::env::current_dir().ok()?.join(".env");
+    path.is_file().then_some(path)
+}
+
+fn credential_source(variable: &str, env_file_contents: &str) -> CredentialSource {
+    let in_env_file = env_file_contents
+        .lines()
+        .any(|line| line.trim_start().starts_with(&format!("{}=", variable)));
+    match (in_env_file, std::env::var_os(variable).is_some()) {
+        (true, _) => CredentialSource::EnvFile,
+        (false, true) => CredentialSource::Environment,
+        (false, false) => CredentialSource::Missing,
+    }
+}
+
+impl Diagnostics {
+    pub async fn collect(app_handle: &tauri::AppHandle) -> Self {
+        let app_store = app_handle.state::<AppStore>();
+        let app_state = app_store.snapshot();
+
+        let (token, granted_scopes) = {
+            let token = app_store.spotify_client.token.lock().await.unwrap();
+            let diagnostics = TokenDiagnostics {
+                present: token.is_some(),
+                has_refresh_token: token.as_ref().map_or(false, |t| t.refresh_token.is_some()),
+                expires_at_ms: token
+                    .as_ref()
+                    .and_then(|t| t.expires_at)
+                    .map(|t| t.timestamp_millis()),
+                expired: token.as_ref().map_or(true, |t| t.is_expired()),
+            };
+            let mut scopes: Vec<String> = token
+                .as_ref()
+                .map(|t| t.scopes.iter().cloned().collect())
+                .unwrap_or_default();
+            scopes.sort();
+            (diagnostics, scopes)
+        };
+
+        let mut missing_scopes: Vec<String> = get_scopes()
+            .into_iter()
+            .filter(|scope| !granted_scopes.contains(scope))
+            .collect();
+        missing_scopes.sort();
+
+        let env_file = env_file();
+        let env_file_contents = env_file
+            .as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let credentials = CREDENTIAL_ENV_VARS
+            .iter()
+            .map(|&variable| CredentialDiagnostics {
+                variable,
+                source: credential_source(variable, &env_file_contents),
+            })
+            .collect();
+
+        let callback_port = settings::get(app_handle).redirect_uri_port;
+
+        let event_loop_running = app_handle
+            .state::<EventLoopHandle>()
+            .0
+            .lock()
+            .unwrap()
+            .is_some();
+
+        Self {
+            app_version: app_handle.package_info().version.to_string(),
+            os: std::env::consts::OS,
+            generated_at_ms: chrono::Utc::now().timestamp_millis(),
+            token,
+            granted_scopes,
+            missing_scopes,
+            last_successful_poll_ms: app_state.last_successful_poll_ms,
+            poll_cadence_ms: playback_call_buffer(app_handle).as_millis() as u64,
+            event_loop_running,
+            rate_limit: RateLimitDiagnostics {
+                limited_until_ms: spotify_request::rate_limited_until_ms(),
+                hits: spotify_request::rate_limit_hits(),
+            },
+            callback_port,
+            callback_port_free: TcpListener::bind(("127.0.0.1", callback_port)).is_ok(),
+            env_file,
+            credentials,
+            store_path: paths::store_path(app_handle),
+            recent_errors: app_handle.state::<ErrorLog>().recent(),
+        }
+    }
+
+    /// Writes the report as pretty JSON next to the logs and returns its path.
+    pub fn write_report(&self, app_handle: &tauri::AppHandle) -> anyhow::Result<PathBuf> {
+        let dir = app_handle.state::<AppPaths>().data_dir.join("diagnostics");
+        std::fs::create_dir_all(&dir)?;
+
+        let path = dir.join(format!("diagnostics-{}.json", self.generated_at_ms));
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,161 @@
+use rspotify::http::HttpError;
+use rspotify::ClientError;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex as SyncMutex;
+use tauri::Manager;
+
+use crate::handlers::HandlerError;
+use crate::state::GetCurrentPlaybackError;
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Auth,
+    Forbidden,
+    NoActiveDevice,
+    RateLimited,
+    SpotifyUnavailable,
+    Network,
+    Spotify,
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn severity(&self) -> Severity {
+        use ErrorKind::*;
+        match self {
+            RateLimited | SpotifyUnavailable | Network => Severity::Warning,
+            Auth | Forbidden | NoActiveDevice | Spotify | Internal => Severity::Error,
+        }
+    }
+
+    pub fn from_status(status: u16) -> Self {
+        use ErrorKind::*;
+        match status {
+            401 => Auth,
+            403 => Forbidden,
+            404 => NoActiveDevice,
+            429 => RateLimited,
+            500..=599 => SpotifyUnavailable,
+            _ => Spotify,
+        }
+    }
+}
+
+pub fn http_status(error: &ClientError) -> Option<u16> {
+    match error {
+        ClientError::Http(error) => match error.as_ref() {
+            HttpError::StatusCode(response) => Some(response.status().as_u16()),
+            HttpError::Client(_) => None,
+        },
+        _ => None,
+    }
+}
+
+impl From<&ClientError> for ErrorKind {
+    fn from(error: &ClientError) -> Self {
+        match (error, http_status(error)) {
+            (_, Some(status)) => Self::from_status(status),
+            (ClientError::Http(_) | ClientError::Io(_), None) => Self::Network,
+            _ => Self::Internal,
+        }
+    }
+}
+
+impl From<&GetCurrentPlaybackError> for ErrorKind {
+    fn from(error: &GetCurrentPlaybackError) -> Self {
+        match error {
+            GetCurrentPlaybackError::SpotifyClientError(error) => error.into(),
+            GetCurrentPlaybackError::TokenNotSet => Self::Auth,
+        }
+    }
+}
+
+impl From<&HandlerError> for ErrorKind {
+    fn from(error: &HandlerError) -> Self {
+        match error {
+            HandlerError::SpotifyClientError(error) => error.into(),
+            HandlerError::GetCurrentPlaybackPlayback(error) => error.into(),
+            HandlerError::OtherError(_) => Self::Internal,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PlaybackError {
+    /// Either `"poll"` or the name of the command that failed.
+    pub source: String,
+    pub severity: Severity,
+    pub kind: ErrorKind,
+    pub message: String,
+    pub timestamp_ms: i64,
+}
+
+impl PlaybackError {
+    pub fn new<E>(source: &str, error: &E) -> Self
+    where
+        E: std::error::Error,
+        for<'a> &'a E: Into<ErrorKind>,
+    {
+        let kind: ErrorKind = error.into();
+        Self {
+            source: source.into(),
+            severity: kind.severity(),
+            kind,
+            message: error.to_string(),
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct ErrorLog(pub SyncMutex<VecDeque<PlaybackError>>);
+
+impl ErrorLog {
+    pub const CAPACITY: usize = 50;
+
+    pub fn record(app_handle: &tauri::AppHandle, error: PlaybackError) {
+        {
+            let error_log = app_handle.state::<ErrorLog>();
+            let mut error_log = error_log.0.lock().unwrap();
+            if error_log.len() >= Self::CAPACITY {
+                error_log.pop_front();
+            }
+            error_log.push_back(error.clone());
+        }
+        match error.severity {
+            Severity::Warning => {
+                tracing::warn!(source = %error.source, kind = ?error.kind, "{}", error.message)
+            }
+            Severity::Error => {
+                tracing::error!(source = %error.source, kind = ?error.kind, "{}", error.message)
+            }
+        }
+        let _ = app_handle.emit_all("playback_error", error);
+    }
+
+    pub fn recent(&self) -> Vec<PlaybackError> {
+        self.0.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Records the error of a failed command before handing the result back to the caller.
+pub fn report<T>(
+    app_handle: &tauri::AppHandle,
+    command: &str,
+    result: Result<T, HandlerError>,
+) -> Result<T, HandlerError> {
+    if let Err(error) = &result {
+        ErrorLog::record(app_handle, PlaybackError::new(command, error));
+    }
+    result
+}
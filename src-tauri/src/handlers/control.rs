@@ -0,0 +1,93 @@
+use rspotify::model::RepeatState;
+use serde::{Deserialize, Serialize};
+use tauri::Manager;
+
+use super::{playback, HandlerError};
+use crate::error_log::report;
+use crate::reauth::ensure_token;
+use crate::state::AppStore;
+
+/// The playback controls every front end offers: the player's buttons and
+/// anything that scripts the app from outside.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlCommand {
+    PlayPause,
+    NextTrack,
+    PrevTrack,
+    ToggleSaved,
+    ToggleShuffle,
+    CycleRepeatState,
+    Seek(u64),
+    /// Seeks from the current position, clamped to the track.
+    SeekBy(i64),
+    SetVolume(u8),
+    /// Changes the volume from the last known one, clamped to 0..=100.
+    ChangeVolume(i16),
+    SetRepeatState(RepeatState),
+}
+
+impl ControlCommand {
+    /// The commands without arguments, which can be run by name.
+    pub const ALL: [ControlCommand; 6] = [
+        ControlCommand::PlayPause,
+        ControlCommand::NextTrack,
+        ControlCommand::PrevTrack,
+        ControlCommand::ToggleSaved,
+        ControlCommand::ToggleShuffle,
+        ControlCommand::CycleRepeatState,
+    ];
+
+    /// Matches the name of the Tauri command that runs it.
+    pub fn name(&self) -> &'static str {
+        use ControlCommand::*;
+        match self {
+            PlayPause => "play_pause",
+            NextTrack => "next_track",
+            PrevTrack => "prev_track",
+            ToggleSaved => "toggle_saved",
+            ToggleShuffle => "toggle_shuffle",
+            CycleRepeatState => "cycle_repeat_state",
+            Seek(_) | SeekBy(_) => "seek",
+            SetVolume(_) | ChangeVolume(_) => "set_volume",
+            SetRepeatState(_) => "set_repeat_state",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|command| command.name() == name)
+    }
+}
+
+/// Runs `command` and records a failure in the error log, whichever front
+/// end it came from. Restores the session first, since callers from outside
+/// may come before the player was ever opened.
+pub async fn execute(
+    app_handle: &tauri::AppHandle,
+    command: ControlCommand,
+) -> Result<(), HandlerError> {
+    use ControlCommand::*;
+    ensure_token(app_handle).await;
+
+    let app_store = app_handle.state::<AppStore>();
+    let result = match command {
+        PlayPause => playback::play_pause(&app_store).await,
+        NextTrack => playback::next_track(&app_store).await,
+        PrevTrack => playback::prev_track(&app_store).await,
+        ToggleSaved => playback::toggle_saved(&app_store).await,
+        ToggleShuffle => playback::toggle_shuffle(&app_store).await,
+        CycleRepeatState => playback::cycle_repeat_state(&app_store).await,
+        Seek(position_ms) => playback::seek(&app_store, position_ms).await,
+        SeekBy(offset_ms) => playback::seek_relative(&app_store, offset_ms).await,
+        SetVolume(volume_percent) => playback::set_volume(&app_store, volume_percent).await,
+        ChangeVolume(offset_percent) => match app_store.snapshot().volume_percent {
+            Some(volume_percent) => {
+                let volume_percent = (volume_percent as i64 + offset_percent as i64).clamp(0, 100);
+                playback::set_volume(&app_store, volume_percent as u8).await
+            }
+            None => Err("Volume not known yet".into()),
+        },
+        SetRepeatState(repeat_state) => playback::set_repeat_state(&app_store, repeat_state).await,
+    };
+    report(app_handle, command.name(), result)
+}
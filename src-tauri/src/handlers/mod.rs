@@ -1,17 +1,35 @@
-use rspotify::model::RepeatState;
+use rspotify::model::Device;
 use rspotify::{prelude::OAuthClient, AuthCodeSpotify};
 use rspotify::{ClientError, ClientResult};
 use serde::Serialize;
-use std::sync::Arc;
-use std::time::Instant;
-use tauri::async_runtime::Mutex;
+use std::path::PathBuf;
+use std::time::Duration;
 use tauri::Manager;
 use tauri_plugin_store::{with_store, StoreCollection};
 use thiserror::Error;
+use tracing::Instrument;
 
+use self::control::ControlCommand;
+use crate::autostart;
+use crate::diagnostics::Diagnostics;
+use crate::error_log::{report, ErrorLog, PlaybackError};
 use crate::helpers::to_string;
+use crate::http_api;
+use crate::notifications::{NotificationOptions, Notifications, STORE_NOTIFICATIONS_KEY};
+use crate::paths;
 use crate::redirect_uri::redirect_uri_web_server;
+use crate::settings::{self, Settings, SettingsState};
+use crate::shortcuts::{self, ShortcutBindings, ShortcutConflict, ShortcutState, Shortcuts};
+use crate::shutdown;
+use crate::spotify_request;
 use crate::state::*;
+use crate::store_file;
+use crate::telemetry::LogLevel;
+use crate::tray::{self, TrayTitle, TrayTitleOptions, STORE_TRAY_TITLE_KEY};
+use crate::window::{self, WindowLayout, WindowMode, WindowSettings};
+
+pub mod control;
+pub mod playback;
 
 #[derive(Error, Serialize, Debug)]
 pub enum HandlerError {
@@ -45,28 +63,30 @@ impl From<&str> for HandlerError {
     }
 }
 
-pub async fn get_token_auto(spotify_oauth: &mut AuthCodeSpotify, port: u16) -> ClientResult<()> {
+pub async fn get_token_auto(spotify_oauth: &AuthCodeSpotify, port: u16) -> ClientResult<()> {
     match redirect_uri_web_server(spotify_oauth, port) {
         Ok(url) => {
             let code = spotify_oauth
                 .parse_response_code(&url)
                 .ok_or_else(|| ClientError::Cli("unable to parse the response code".to_string()))?;
-            spotify_oauth.request_token(&code).await
+            let code = &code;
+            spotify_request::send("request_token", move || spotify_oauth.request_token(code)).await
         }
         Err(()) => Ok(()),
     }
 }
 
 #[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "login_spotify"))]
 pub async fn login_spotify(
     app_handle: tauri::AppHandle,
     app_store: tauri::State<'_, AppStore>,
 ) -> Result<(), String> {
-    let app_state = app_store.0.lock().await;
-    let mut spotify = app_state.spotify_client.lock().await;
+    let spotify = &app_store.spotify_client;
 
     if spotify.token.clone().lock().await.unwrap().is_none() {
-        get_token_auto(&mut spotify, 8585).await.unwrap();
+        let port = settings::get(&app_handle).redirect_uri_port;
+        get_token_auto(spotify, port).await.unwrap();
 
         let token_arc = spotify.token.clone();
         let token = token_arc.lock().await.unwrap();
@@ -76,7 +96,7 @@ pub async fn login_spotify(
         with_store(
             &app_handle,
             collection,
-            STORE_PATH_BUF.parse().unwrap(),
+            paths::store_path(&app_handle),
             |store| {
                 Ok(store
                     .cache
@@ -84,191 +104,342 @@ pub async fn login_spotify(
             },
         )
         .unwrap();
+        if let Err(error) = store_file::save_now(&app_handle) {
+            tracing::error!(%error, "failed to save token");
+        }
     }
 
     Ok(())
 }
 
 #[tauri::command]
-pub async fn play_pause(
+#[tracing::instrument(skip_all, fields(command = "seek"))]
+pub async fn seek(position_ms: u64, app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    control::execute(&app_handle, ControlCommand::Seek(position_ms)).await
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_queue"))]
+pub async fn get_queue(
+    app_handle: tauri::AppHandle,
+    app_store: tauri::State<'_, AppStore>,
+) -> Result<Vec<SimplifiedItem>, HandlerError> {
+    report(&app_handle, "get_queue", playback::queue(&app_store).await)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_devices"))]
+pub async fn get_devices(
+    app_handle: tauri::AppHandle,
+    app_store: tauri::State<'_, AppStore>,
+) -> Result<Vec<Device>, HandlerError> {
+    report(
+        &app_handle,
+        "get_devices",
+        playback::devices(&app_store).await,
+    )
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "transfer_playback"))]
+pub async fn transfer_playback(
+    device_id: String,
     app_handle: tauri::AppHandle,
     app_store: tauri::State<'_, AppStore>,
 ) -> Result<(), HandlerError> {
-    let mut app_state = app_store.0.lock().await;
+    report(
+        &app_handle,
+        "transfer_playback",
+        playback::transfer_playback(&app_store, &device_id).await,
+    )
+}
 
-    {
-        let prev_app_state = app_state.clone();
-        let spotify_client = app_state.spotify_client.clone();
-        let spotify_client = spotify_client.lock().await;
-
-        let result = if app_state.playing {
-            app_state.playing = false;
-            app_state.emit_update(&app_handle);
-            (*spotify_client).pause_playback(None).await
-        } else {
-            app_state.playing = true;
-            app_state.emit_update(&app_handle);
-            let progress = Some(app_state.progress_ms as u32);
-            let device_id = app_state.device_id.as_deref();
-            (*spotify_client).resume_playback(device_id, progress).await
-        };
-
-        if result.is_err() {
-            *app_state = prev_app_state;
-            app_state.emit_update(&app_handle);
-            result?;
-        }
-    }
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_shortcuts"))]
+pub async fn get_shortcuts(
+    shortcuts: tauri::State<'_, Shortcuts>,
+) -> Result<ShortcutState, HandlerError> {
+    Ok(shortcuts.0.lock().unwrap().clone())
+}
 
-    app_state.get_current_playback().await?;
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "set_shortcuts"))]
+pub async fn set_shortcuts(
+    bindings: ShortcutBindings,
+    app_handle: tauri::AppHandle,
+) -> Result<Vec<ShortcutConflict>, HandlerError> {
+    shortcuts::save_bindings(&app_handle, &bindings)?;
+    Ok(shortcuts::register(&app_handle, bindings))
+}
 
-    Ok(())
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_app_state"))]
+pub async fn get_app_state(
+    app_store: tauri::State<'_, AppStore>,
+) -> Result<AppState, HandlerError> {
+    Ok(app_store.snapshot())
 }
 
 #[tauri::command]
-pub async fn next_track(app_store: tauri::State<'_, AppStore>) -> Result<(), HandlerError> {
-    let mut app_state = app_store.0.lock().await;
+#[tracing::instrument(skip_all, fields(command = "play_pause"))]
+pub async fn play_pause(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    control::execute(&app_handle, ControlCommand::PlayPause).await
+}
 
-    {
-        let spotify_client = app_state.spotify_client.lock().await;
-        (*spotify_client).next_track(None).await?;
-    }
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "next_track"))]
+pub async fn next_track(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    control::execute(&app_handle, ControlCommand::NextTrack).await
+}
 
-    app_state.get_current_playback().await?;
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "prev_track"))]
+pub async fn prev_track(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    control::execute(&app_handle, ControlCommand::PrevTrack).await
+}
 
-    Ok(())
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "toggle_saved"))]
+pub async fn toggle_saved(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    control::execute(&app_handle, ControlCommand::ToggleSaved).await
 }
 
 #[tauri::command]
-pub async fn prev_track(app_store: tauri::State<'_, AppStore>) -> Result<(), HandlerError> {
-    let mut app_state = app_store.0.lock().await;
+#[tracing::instrument(skip_all, fields(command = "toggle_shuffle"))]
+pub async fn toggle_shuffle(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    control::execute(&app_handle, ControlCommand::ToggleShuffle).await
+}
 
-    {
-        let spotify_client = app_state.spotify_client.lock().await;
-        (*spotify_client).previous_track(None).await?;
-    }
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "cycle_repeat_state"))]
+pub async fn cycle_repeat_state(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    control::execute(&app_handle, ControlCommand::CycleRepeatState).await
+}
 
-    app_state.get_current_playback().await?;
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_log_level"))]
+pub async fn get_log_level(log_level: tauri::State<'_, LogLevel>) -> Result<String, HandlerError> {
+    Ok(log_level.get())
+}
 
-    Ok(())
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "set_log_level"))]
+pub async fn set_log_level(
+    directive: String,
+    log_level: tauri::State<'_, LogLevel>,
+) -> Result<(), HandlerError> {
+    Ok(log_level.set(&directive)?)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_diagnostics"))]
+pub async fn get_diagnostics(app_handle: tauri::AppHandle) -> Result<Diagnostics, HandlerError> {
+    Ok(Diagnostics::collect(&app_handle).await)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "write_diagnostics_report"))]
+pub async fn write_diagnostics_report(
+    app_handle: tauri::AppHandle,
+) -> Result<PathBuf, HandlerError> {
+    let diagnostics = Diagnostics::collect(&app_handle).await;
+    let path = diagnostics
+        .write_report(&app_handle)
+        .map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_tray_title_options"))]
+pub async fn get_tray_title_options(
+    tray_title: tauri::State<'_, TrayTitle>,
+) -> Result<TrayTitleOptions, HandlerError> {
+    Ok(tray_title.0.lock().unwrap().clone())
 }
 
 #[tauri::command]
-pub async fn toggle_saved(
+#[tracing::instrument(skip_all, fields(command = "set_tray_title_options"))]
+pub async fn set_tray_title_options(
+    options: TrayTitleOptions,
     app_handle: tauri::AppHandle,
     app_store: tauri::State<'_, AppStore>,
+    tray_title: tauri::State<'_, TrayTitle>,
 ) -> Result<(), HandlerError> {
-    let mut app_state = app_store.0.lock().await;
-    let prev_app_state = app_state.clone();
-
-    let spotify_client = app_state.spotify_client.clone();
-    let spotify_client = spotify_client.lock().await;
-
-    let current = match &mut app_state.curr {
-        Some(current) => current,
-        None => return Err("No current playback".into()),
-    };
-
-    let id = match current.id.clone() {
-        Some(PlayableId::Track(id)) => id,
-        Some(PlayableId::Episode(_)) => return Err("Current playback not a track".into()),
-        _ => return Err("Current playback has no `id`".into()),
-    };
-
-    let result = if current.saved {
-        current.saved = false;
-        app_state.emit_update(&app_handle);
-        (*spotify_client)
-            .current_user_saved_tracks_delete(vec![id])
-            .await
-    } else {
-        current.saved = true;
-        app_state.emit_update(&app_handle);
-        (*spotify_client)
-            .current_user_saved_tracks_add(vec![id])
-            .await
-    };
-
-    if result.is_err() {
-        *app_state = prev_app_state;
-        app_state.emit_update(&app_handle);
-        result?;
-    }
+    *tray_title.0.lock().unwrap() = options.clone();
+
+    let serialized_options = serde_json::to_value(&options).map_err(|e| e.to_string())?;
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        &app_handle,
+        collection,
+        paths::store_path(&app_handle),
+        |store| {
+            Ok(store
+                .cache
+                .insert(STORE_TRAY_TITLE_KEY.to_string(), serialized_options))
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    store_file::schedule_save(&app_handle);
+
+    tray::update_title(&app_handle, &app_store.snapshot());
+    Ok(())
+}
 
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_notification_options"))]
+pub async fn get_notification_options(
+    notifications: tauri::State<'_, Notifications>,
+) -> Result<NotificationOptions, HandlerError> {
+    Ok(notifications.0.lock().unwrap().clone())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "set_notification_options"))]
+pub async fn set_notification_options(
+    options: NotificationOptions,
+    app_handle: tauri::AppHandle,
+    notifications: tauri::State<'_, Notifications>,
+) -> Result<(), HandlerError> {
+    *notifications.0.lock().unwrap() = options.clone();
+
+    let serialized_options = serde_json::to_value(&options).map_err(|e| e.to_string())?;
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        &app_handle,
+        collection,
+        paths::store_path(&app_handle),
+        |store| {
+            Ok(store
+                .cache
+                .insert(STORE_NOTIFICATIONS_KEY.to_string(), serialized_options))
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    store_file::schedule_save(&app_handle);
     Ok(())
 }
 
 #[tauri::command]
-pub async fn toggle_shuffle(
+#[tracing::instrument(skip_all, fields(command = "get_window_mode"))]
+pub async fn get_window_mode(
+    window_settings: tauri::State<'_, WindowSettings>,
+) -> Result<WindowMode, HandlerError> {
+    Ok(window_settings.0.lock().unwrap().mode)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "set_window_mode"))]
+pub async fn set_window_mode(
+    mode: WindowMode,
     app_handle: tauri::AppHandle,
-    app_store: tauri::State<'_, AppStore>,
 ) -> Result<(), HandlerError> {
-    let mut app_state = app_store.0.lock().await;
+    window::set_mode(&app_handle, mode)?;
+    Ok(())
+}
 
-    {
-        let prev_app_state = app_state.clone();
-        let spotify_client = app_state.spotify_client.clone();
-        let spotify_client = spotify_client.lock().await;
-
-        app_state.shuffle = !app_state.shuffle;
-        app_state.emit_update(&app_handle);
-        let result = (*spotify_client)
-            .shuffle(app_state.shuffle, app_state.device_id.as_deref())
-            .await;
-
-        if result.is_err() {
-            *app_state = prev_app_state;
-            app_state.emit_update(&app_handle);
-            result?;
-        }
-    }
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_window_layout"))]
+pub async fn get_window_layout(
+    window_settings: tauri::State<'_, WindowSettings>,
+) -> Result<WindowLayout, HandlerError> {
+    Ok(window_settings.0.lock().unwrap().layout)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "set_window_layout"))]
+pub async fn set_window_layout(
+    layout: WindowLayout,
+    app_handle: tauri::AppHandle,
+) -> Result<(), HandlerError> {
+    window::set_layout(&app_handle, layout)?;
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_autostart"))]
+pub async fn get_autostart(app_handle: tauri::AppHandle) -> Result<bool, HandlerError> {
+    Ok(autostart::is_enabled(&app_handle))
+}
 
-    app_state.get_current_playback().await?;
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "enable_autostart"))]
+pub async fn enable_autostart(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    autostart::enable(&app_handle).map_err(|e| e.to_string())?;
+    Ok(())
+}
 
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "disable_autostart"))]
+pub async fn disable_autostart(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    autostart::disable(&app_handle).map_err(|e| e.to_string())?;
     Ok(())
 }
 
 #[tauri::command]
-pub async fn cycle_repeat_state(
+#[tracing::instrument(skip_all, fields(command = "get_settings"))]
+pub async fn get_settings(
+    settings: tauri::State<'_, SettingsState>,
+) -> Result<Settings, HandlerError> {
+    Ok(settings.0.lock().unwrap().clone())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_http_api_token"))]
+pub async fn get_http_api_token(app_handle: tauri::AppHandle) -> Result<String, HandlerError> {
+    let token = http_api::load_or_create_token(&app_handle).map_err(|e| e.to_string())?;
+    Ok(token)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "update_settings"))]
+pub async fn update_settings(
+    settings: Settings,
     app_handle: tauri::AppHandle,
-    app_store: tauri::State<'_, AppStore>,
 ) -> Result<(), HandlerError> {
-    let mut app_state = app_store.0.lock().await;
+    settings::update(&app_handle, settings)?;
+    Ok(())
+}
 
-    {
-        let prev_app_state = app_state.clone();
-        let spotify_client = app_state.spotify_client.clone();
-        let spotify_client = spotify_client.lock().await;
-
-        use RepeatState::*;
-        app_state.repeat_state = match &app_state.repeat_state {
-            Off => Context,
-            Context => Track,
-            Track => Off,
-        };
-        app_state.emit_update(&app_handle);
-
-        let result = (*spotify_client)
-            .repeat(app_state.repeat_state, app_state.device_id.as_deref())
-            .await;
-
-        if result.is_err() {
-            *app_state = prev_app_state;
-            app_state.emit_update(&app_handle);
-            result?;
-        }
-    }
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "quit"))]
+pub async fn quit(app_handle: tauri::AppHandle) -> Result<(), HandlerError> {
+    shutdown::quit(app_handle).await;
+    Ok(())
+}
 
-    app_state.get_current_playback().await?;
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(command = "get_recent_errors"))]
+pub async fn get_recent_errors(
+    error_log: tauri::State<'_, ErrorLog>,
+) -> Result<Vec<PlaybackError>, HandlerError> {
+    Ok(error_log.recent())
+}
 
-    Ok(())
+pub fn window_open(app_handle: &tauri::AppHandle) -> bool {
+    app_handle.get_window(window::MAIN_WINDOW).is_some()
+}
+
+/// Polls less often while the popup is closed; the tray still needs to
+/// notice track changes then.
+pub fn playback_call_buffer(app_handle: &tauri::AppHandle) -> Duration {
+    let settings = settings::get(app_handle);
+    match window_open(app_handle) {
+        true => settings.playback_call_buffer(),
+        false => settings.background_playback_call_buffer(),
+    }
 }
 
-async fn update(app_handle: Arc<Mutex<tauri::AppHandle>>) -> anyhow::Result<()> {
-    let app_handle = (*app_handle).lock().await;
+fn seek_call_buffer(app_handle: &tauri::AppHandle) -> Duration {
+    match window_open(app_handle) {
+        true => AppState::SEEK_CALL_BUFFER,
+        false => AppState::BACKGROUND_SEEK_CALL_BUFFER,
+    }
+}
 
-    let app_state = app_handle.state::<AppStore>().0.clone();
-    let mut app_state = app_state.lock().await;
+async fn update(app_handle: &tauri::AppHandle) -> Result<(), GetCurrentPlaybackError> {
+    let app_store = app_handle.state::<AppStore>();
+    let app_state = app_store.snapshot();
 
     let duration = app_state
         .curr
@@ -276,41 +447,38 @@ async fn update(app_handle: Arc<Mutex<tauri::AppHandle>>) -> anyhow::Result<()>
         .map(|c| c.duration_ms)
         .unwrap_or(u64::MAX);
 
-    if app_state.last_playback_call.elapsed() >= AppState::PLAYBACK_CALL_BUFFER
+    if app_state.last_playback_call.elapsed() >= playback_call_buffer(app_handle)
         || app_state.progress_ms >= duration
     {
-        app_state.get_current_playback().await?;
+        app_store
+            .get_current_playback()
+            .instrument(tracing::debug_span!("poll_cycle"))
+            .await?;
     }
 
-    if app_state.curr.is_some()
-        && app_state.last_seek_update.elapsed() >= AppState::SEEK_CALL_BUFFER
-        && app_state.playing
-    {
-        let elapsed = app_state.last_seek_update.elapsed().as_millis();
-        let diff = (elapsed / AppState::SEEK_CALL_BUFFER.as_millis()) as u64;
-        let curr = app_state.curr.as_mut().unwrap();
-        curr.progress_ms += diff;
-        app_state.progress_ms = curr.progress_ms;
-        app_state.last_seek_update = Instant::now();
+    if app_state.curr.is_some() && app_state.playing {
+        app_store.update(AppState::advance_progress).await;
     }
 
-    app_state.emit_update(&app_handle);
-
     Ok(())
 }
 
 pub fn subscribe_to_event_loop(app_handle: &tauri::AppHandle) -> Result<(), String> {
     let event_loop_handle = app_handle.state::<EventLoopHandle>();
-    let app_handle_mutex = Arc::new(Mutex::new(app_handle.clone()));
+    let app_handle = app_handle.clone();
 
     let mut event_loop_handle = event_loop_handle.0.lock().unwrap();
+    if event_loop_handle.is_some() {
+        return Ok(());
+    }
+
     *event_loop_handle = Some(tauri::async_runtime::spawn(async move {
         loop {
-            let status = update(app_handle_mutex.clone()).await;
-            #[cfg(debug_assertions)]
-            if let Err(error) = status {
-                println!("Error in event loop.\n\tError: {}", error);
+            if let Err(error) = update(&app_handle).await {
+                ErrorLog::record(&app_handle, PlaybackError::new("poll", &error));
+                tokio::time::sleep(AppState::ERROR_BACKOFF).await;
             }
+            tokio::time::sleep(seek_call_buffer(&app_handle)).await;
         }
     }));
 
@@ -320,9 +488,9 @@ pub fn subscribe_to_event_loop(app_handle: &tauri::AppHandle) -> Result<(), Stri
 pub fn unsubscribe_to_event_loop(app_handle: &tauri::AppHandle) -> Result<(), String> {
     let event_loop_handle = app_handle.state::<EventLoopHandle>();
 
-    let event_loop_handle = event_loop_handle.0.lock().unwrap();
+    let mut event_loop_handle = event_loop_handle.0.lock().unwrap();
 
-    if let Some(event_loop_handle) = &*event_loop_handle {
+    if let Some(event_loop_handle) = event_loop_handle.take() {
         event_loop_handle.abort();
     }
 
@@ -0,0 +1,385 @@
+use rspotify::model::PlayableId as SpotifyPlayableId;
+use rspotify::model::{
+    AlbumId, ArtistId, Device, EpisodeId, PlayContextId, PlaylistId, RepeatState, ShowId, TrackId,
+};
+use rspotify::prelude::OAuthClient;
+
+use super::HandlerError;
+use crate::spotify_request;
+use crate::state::*;
+
+pub async fn play_pause(app_store: &AppStore) -> Result<(), HandlerError> {
+    let prev_app_state = app_store.edit(|state| state.playing = !state.playing).await;
+    let spotify_client = &app_store.spotify_client;
+
+    let result = if prev_app_state.playing {
+        spotify_request::send("pause_playback", move || {
+            spotify_client.pause_playback(None)
+        })
+        .await
+    } else {
+        let progress = Some(prev_app_state.progress_ms as u32);
+        let device_id = prev_app_state.device_id.as_deref();
+        spotify_request::send("resume_playback", move || {
+            spotify_client.resume_playback(device_id, progress)
+        })
+        .await
+    };
+
+    if result.is_err() {
+        let playing = prev_app_state.playing;
+        app_store.edit(move |state| state.playing = playing).await;
+        result?;
+    }
+
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+pub async fn next_track(app_store: &AppStore) -> Result<(), HandlerError> {
+    let spotify_client = &app_store.spotify_client;
+    spotify_request::send("next_track", move || spotify_client.next_track(None)).await?;
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+pub async fn prev_track(app_store: &AppStore) -> Result<(), HandlerError> {
+    let spotify_client = &app_store.spotify_client;
+    spotify_request::send("previous_track", move || {
+        spotify_client.previous_track(None)
+    })
+    .await?;
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+pub async fn toggle_saved(app_store: &AppStore) -> Result<(), HandlerError> {
+    let app_state = app_store.snapshot();
+
+    let current = match &app_state.curr {
+        Some(current) => current,
+        None => return Err("No current playback".into()),
+    };
+
+    let id = match current.id.clone() {
+        Some(PlayableId::Track(id)) => id,
+        Some(PlayableId::Episode(_)) => return Err("Current playback not a track".into()),
+        _ => return Err("Current playback has no `id`".into()),
+    };
+
+    let saved = !current.saved;
+    let set_saved = |id: TrackId<'static>, saved: bool| {
+        move |state: &mut AppState| {
+            state.saved_cache.invalidate(&id);
+            if let Some(curr) = state.curr.as_mut() {
+                if curr.id == Some(PlayableId::Track(id)) {
+                    curr.saved = saved;
+                }
+            }
+        }
+    };
+    app_store.edit(set_saved(id.clone(), saved)).await;
+
+    let spotify_client = &app_store.spotify_client;
+    let track_id = &id;
+    let result = if saved {
+        spotify_request::send("current_user_saved_tracks_add", move || {
+            spotify_client.current_user_saved_tracks_add(vec![track_id.clone()])
+        })
+        .await
+    } else {
+        spotify_request::send("current_user_saved_tracks_delete", move || {
+            spotify_client.current_user_saved_tracks_delete(vec![track_id.clone()])
+        })
+        .await
+    };
+
+    if result.is_err() {
+        app_store.edit(set_saved(id, !saved)).await;
+        result?;
+    } else {
+        app_store
+            .update(move |state| state.saved_cache.insert(id, saved))
+            .await;
+    }
+
+    Ok(())
+}
+
+pub async fn toggle_shuffle(app_store: &AppStore) -> Result<(), HandlerError> {
+    let prev_app_state = app_store.edit(|state| state.shuffle = !state.shuffle).await;
+
+    let spotify_client = &app_store.spotify_client;
+    let shuffle = !prev_app_state.shuffle;
+    let device_id = prev_app_state.device_id.as_deref();
+    let result = spotify_request::send("shuffle", move || {
+        spotify_client.shuffle(shuffle, device_id)
+    })
+    .await;
+
+    if result.is_err() {
+        let shuffle = prev_app_state.shuffle;
+        app_store.edit(move |state| state.shuffle = shuffle).await;
+        result?;
+    }
+
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+pub async fn set_repeat_state(
+    app_store: &AppStore,
+    repeat_state: RepeatState,
+) -> Result<(), HandlerError> {
+    let prev_app_state = app_store
+        .edit(move |state| state.repeat_state = repeat_state)
+        .await;
+
+    let spotify_client = &app_store.spotify_client;
+    let device_id = prev_app_state.device_id.as_deref();
+    let result = spotify_request::send("repeat", move || {
+        spotify_client.repeat(repeat_state, device_id)
+    })
+    .await;
+
+    if result.is_err() {
+        let repeat_state = prev_app_state.repeat_state;
+        app_store
+            .edit(move |state| state.repeat_state = repeat_state)
+            .await;
+        result?;
+    }
+
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+pub async fn cycle_repeat_state(app_store: &AppStore) -> Result<(), HandlerError> {
+    use RepeatState::*;
+    let repeat_state = match app_store.snapshot().repeat_state {
+        Off => Context,
+        Context => Track,
+        Track => Off,
+    };
+
+    set_repeat_state(app_store, repeat_state).await
+}
+
+pub async fn set_volume(app_store: &AppStore, volume_percent: u8) -> Result<(), HandlerError> {
+    let prev_app_state = app_store
+        .edit(move |state| state.volume_percent = Some(volume_percent as u32))
+        .await;
+
+    let spotify_client = &app_store.spotify_client;
+    let device_id = prev_app_state.device_id.as_deref();
+    let result = spotify_request::send("volume", move || {
+        spotify_client.volume(volume_percent, device_id)
+    })
+    .await;
+
+    if result.is_err() {
+        let volume_percent = prev_app_state.volume_percent;
+        app_store
+            .edit(move |state| state.volume_percent = volume_percent)
+            .await;
+        result?;
+    }
+
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+pub async fn seek(app_store: &AppStore, position_ms: u64) -> Result<(), HandlerError> {
+    let set_progress = |progress_ms: u64| {
+        move |state: &mut AppState| {
+            state.progress_ms = progress_ms;
+            if let Some(curr) = state.curr.as_mut() {
+                curr.progress_ms = progress_ms;
+            }
+        }
+    };
+    let prev_app_state = app_store.edit(set_progress(position_ms)).await;
+
+    let spotify_client = &app_store.spotify_client;
+    let device_id = prev_app_state.device_id.as_deref();
+    let result = spotify_request::send("seek_track", move || {
+        spotify_client.seek_track(position_ms as u32, device_id)
+    })
+    .await;
+
+    if result.is_err() {
+        app_store
+            .edit(set_progress(prev_app_state.progress_ms))
+            .await;
+        result?;
+    }
+
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+/// Seeks by `offset_ms` from the current position, clamped to the track.
+pub async fn seek_relative(app_store: &AppStore, offset_ms: i64) -> Result<(), HandlerError> {
+    let app_state = app_store.snapshot();
+    let duration_ms = match &app_state.curr {
+        Some(current) => current.duration_ms as i64,
+        None => return Err("No current playback".into()),
+    };
+    let position_ms = (app_state.progress_ms as i64 + offset_ms).clamp(0, duration_ms);
+
+    seek(app_store, position_ms as u64).await
+}
+
+/// The items queued after the current one, in play order.
+pub async fn queue(app_store: &AppStore) -> Result<Vec<SimplifiedItem>, HandlerError> {
+    let spotify_client = &app_store.spotify_client;
+    let queue = spotify_request::fetch("current_user_queue", move || {
+        spotify_client.current_user_queue()
+    })
+    .await?;
+
+    Ok(queue.queue.into_iter().map(SimplifiedItem::from).collect())
+}
+
+pub async fn devices(app_store: &AppStore) -> Result<Vec<Device>, HandlerError> {
+    let spotify_client = &app_store.spotify_client;
+    let devices = spotify_request::fetch("device", move || spotify_client.device()).await?;
+
+    Ok(devices)
+}
+
+pub async fn transfer_playback(app_store: &AppStore, device_id: &str) -> Result<(), HandlerError> {
+    let spotify_client = &app_store.spotify_client;
+    spotify_request::send("transfer_playback", move || {
+        spotify_client.transfer_playback(device_id, None)
+    })
+    .await?;
+
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+/// What a `spotify:<type>:<id>` URI points at.
+#[derive(Debug)]
+enum UriTarget<'a> {
+    /// A track or episode, played on its own.
+    Item(SpotifyPlayableId<'a>),
+    /// Anything else, played from its start.
+    Context(PlayContextId<'a>),
+}
+
+fn parse_uri(uri: &str) -> Result<UriTarget<'_>, HandlerError> {
+    let invalid = || HandlerError::from(format!("Invalid Spotify URI: {}", uri));
+    let (kind, id) = match uri.split(':').collect::<Vec<_>>()[..] {
+        ["spotify", kind, id] => (kind, id),
+        _ => return Err(invalid()),
+    };
+
+    let target = match kind {
+        "track" => TrackId::from_id(id).map(|id| UriTarget::Item(SpotifyPlayableId::Track(id))),
+        "episode" => {
+            EpisodeId::from_id(id).map(|id| UriTarget::Item(SpotifyPlayableId::Episode(id)))
+        }
+        "album" => AlbumId::from_id(id).map(|id| UriTarget::Context(PlayContextId::Album(id))),
+        "artist" => ArtistId::from_id(id).map(|id| UriTarget::Context(PlayContextId::Artist(id))),
+        "playlist" => {
+            PlaylistId::from_id(id).map(|id| UriTarget::Context(PlayContextId::Playlist(id)))
+        }
+        "show" => ShowId::from_id(id).map(|id| UriTarget::Context(PlayContextId::Show(id))),
+        _ => return Err(invalid()),
+    };
+    target.map_err(|_| invalid())
+}
+
+/// Starts playing a `spotify:<type>:<id>` URI. Tracks and episodes are played
+/// on their own, anything else is played as a context from its start.
+pub async fn play_uri(app_store: &AppStore, uri: &str) -> Result<(), HandlerError> {
+    let target = parse_uri(uri)?;
+
+    let spotify_client = &app_store.spotify_client;
+    let device_id = app_store.snapshot().device_id;
+    let device_id = device_id.as_deref();
+    match target {
+        UriTarget::Item(playable) => {
+            spotify_request::send("start_uris_playback", move || {
+                spotify_client.start_uris_playback([playable.clone()], device_id, None, None)
+            })
+            .await?;
+        }
+        UriTarget::Context(context) => {
+            spotify_request::send("start_context_playback", move || {
+                spotify_client.start_context_playback(context.clone(), device_id, None, None)
+            })
+            .await?;
+        }
+    }
+
+    app_store.get_current_playback().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rspotify::prelude::Id;
+
+    #[test]
+    fn tracks_and_episodes_are_items() {
+        let target = parse_uri("spotify:track:4iV5W9uYEdYUVa79Axb7Rh").unwrap();
+        assert!(matches!(
+            target,
+            UriTarget::Item(SpotifyPlayableId::Track(id)) if id.id() == "4iV5W9uYEdYUVa79Axb7Rh"
+        ));
+        let target = parse_uri("spotify:episode:512ojhOuo1ktJprKbVcKyQ").unwrap();
+        assert!(matches!(
+            target,
+            UriTarget::Item(SpotifyPlayableId::Episode(_))
+        ));
+    }
+
+    #[test]
+    fn everything_else_is_a_context() {
+        let target = parse_uri("spotify:album:0sNOF9WDwhWunNAHPD3Baj").unwrap();
+        assert!(matches!(
+            target,
+            UriTarget::Context(PlayContextId::Album(id)) if id.id() == "0sNOF9WDwhWunNAHPD3Baj"
+        ));
+        for (uri, kind) in [
+            ("spotify:artist:0OdUWJ0sBjDrqHygGUXeCF", "artist"),
+            ("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M", "playlist"),
+            ("spotify:show:5CfCWKI5pZ28U0uOzXkDHe", "show"),
+        ] {
+            let target = parse_uri(uri).unwrap();
+            let parsed = match target {
+                UriTarget::Context(PlayContextId::Artist(_)) => "artist",
+                UriTarget::Context(PlayContextId::Playlist(_)) => "playlist",
+                UriTarget::Context(PlayContextId::Show(_)) => "show",
+                _ => "other",
+            };
+            assert_eq!(parsed, kind);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_uris() {
+        for uri in [
+            "",
+            "spotify:track",
+            "spotify:track:4iV5W9uYEdYUVa79Axb7Rh:extra",
+            "https://open.spotify.com/track/4iV5W9uYEdYUVa79Axb7Rh",
+            "spotify:user:someone",
+            "spotify:track:not-an-id!",
+        ] {
+            assert!(parse_uri(uri).is_err(), "{}", uri);
+        }
+    }
+}
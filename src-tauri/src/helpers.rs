@@ -1,4 +1,5 @@
 use serde::Serializer;
+use std::path::PathBuf;
 
 #[macro_export]
 macro_rules! unwrap_or {
@@ -17,3 +18,50 @@ where
 {
     s.serialize_str(error.to_string().as_str())
 }
+
+/// The binary the OS should start for this app. AppImages run from a
+/// temporary mount, so the image itself has to be started instead.
+pub fn executable() -> anyhow::Result<PathBuf> {
+    if let Some(app_image) = std::env::var_os("APPIMAGE") {
+        return Ok(app_image.into());
+    }
+    Ok(std::env::current_exe()?)
+}
+
+/// Quotes an `Exec` argument as the desktop entry spec asks for.
+#[cfg(target_os = "linux")]
+pub fn quote_exec_arg(arg: &str) -> String {
+    let escaped: String = arg
+        .chars()
+        .flat_map(|c| match c {
+            '"' | '`' | '$' | '\\' => vec!['\\', c],
+            _ => vec![c],
+        })
+        .collect();
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::quote_exec_arg;
+
+    #[test]
+    fn quotes_plain_paths() {
+        assert_eq!(
+            quote_exec_arg("/usr/bin/spotify-mini-player"),
+            r#""/usr/bin/spotify-mini-player""#
+        );
+        assert_eq!(
+            quote_exec_arg("/home/me/My Apps/player"),
+            r#""/home/me/My Apps/player""#
+        );
+    }
+
+    #[test]
+    fn escapes_reserved_characters() {
+        assert_eq!(
+            quote_exec_arg(r#"/a "b" `c` $d \e"#),
+            r#""/a \"b\" \`c\` \$d \\e""#
+        );
+    }
+}
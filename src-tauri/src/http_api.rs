@@ -0,0 +1,385 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, State};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use rand::distributions::{Alphanumeric, DistString};
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex as SyncMutex};
+use std::time::{Duration, Instant};
+use tauri::async_runtime::JoinHandle;
+use tauri::Manager;
+
+use crate::handlers::control::{self, ControlCommand};
+use crate::paths::AppPaths;
+use crate::settings::HttpApiSettings;
+use crate::state::{AppState, AppStore, PlaybackEvent};
+
+const TOKEN_FILE_NAME: &str = "http-api-token";
+const TOKEN_LENGTH: usize = 32;
+
+/// How often the stream sends the interpolated progress while playing. The
+/// state itself changes far more often than an overlay needs.
+const STREAM_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The running server and the settings it was started with.
+#[derive(Default)]
+pub struct HttpApi(SyncMutex<Option<(HttpApiSettings, JoinHandle<()>)>>);
+
+#[derive(Clone)]
+struct ApiState {
+    app_handle: tauri::AppHandle,
+}
+
+/// What a request has to present to get past [`authorize`].
+#[derive(Clone)]
+struct Auth {
+    token: Arc<String>,
+    allowed_origins: Arc<Vec<String>>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+fn error_response(status: StatusCode, error: impl ToString) -> Response {
+    let body = ErrorBody {
+        error: error.to_string(),
+    };
+    (status, Json(body)).into_response()
+}
+
+/// Kept as a file of its own, so scripts can read it without parsing the
+/// store, e.g. `curl -H "Authorization: Bearer $(cat <config dir>/http-api-token)"`.
+pub fn token_path(app_handle: &tauri::AppHandle) -> PathBuf {
+    app_handle
+        .state::<AppPaths>()
+        .config_dir
+        .join(TOKEN_FILE_NAME)
+}
+
+/// Reads the token, creating one that only the user can read on first use.
+pub fn load_or_create_token(app_handle: &tauri::AppHandle) -> anyhow::Result<String> {
+    let path = token_path(app_handle);
+    if let Ok(token) = std::fs::read_to_string(&path) {
+        let token = token.trim();
+        if !token.is_empty() {
+            return Ok(token.to_string());
+        }
+    }
+
+    let token = Alphanumeric.sample_string(&mut rand::thread_rng(), TOKEN_LENGTH);
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(&mut options.open(&path)?, token.as_bytes())?;
+    tracing::info!(path = %path.display(), "created http api token");
+    Ok(token)
+}
+
+/// Compares in constant time, so the token can't be guessed byte by byte.
+fn token_matches(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Browsers can't set headers on a WebSocket, so the token may also come
+/// as a `token` query parameter.
+fn given_token<B>(request: &Request<B>) -> Option<String> {
+    let header = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if let Some(token) = header {
+        return Some(token.to_string());
+    }
+
+    let query = request.uri().query()?;
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "token")
+        .map(|(_, token)| token.into_owned())
+}
+
+/// Checks the origin before the token, so a page that isn't allowed can't
+/// probe for it.
+async fn authorize<B>(State(auth): State<Auth>, request: Request<B>, next: Next<B>) -> Response {
+    if let Some(origin) = request.headers().get(header::ORIGIN) {
+        let allowed = origin.to_str().map_or(false, |origin| {
+            auth.allowed_origins.iter().any(|allowed| allowed == origin)
+        });
+        if !allowed {
+            return error_response(StatusCode::FORBIDDEN, "origin not allowed");
+        }
+    }
+
+    let authorized =
+        given_token(&request).map_or(false, |given| token_matches(&given, &auth.token));
+    if !authorized {
+        return error_response(StatusCode::UNAUTHORIZED, "missing or wrong token");
+    }
+    next.run(request).await
+}
+
+async fn get_state(State(state): State<ApiState>) -> Json<AppState> {
+    Json(state.app_handle.state::<AppStore>().snapshot())
+}
+
+async fn run_command(State(state): State<ApiState>, Path(name): Path<String>) -> Response {
+    let command = match ControlCommand::from_name(&name) {
+        Some(command) => command,
+        None => {
+            return error_response(StatusCode::NOT_FOUND, format!("unknown command `{}`", name))
+        }
+    };
+    match control::execute(&state.app_handle, command).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(error) => error_response(StatusCode::BAD_GATEWAY, error),
+    }
+}
+
+fn message(event: &PlaybackEvent) -> Message {
+    Message::Text(serde_json::to_string(event).unwrap())
+}
+
+/// Sends the whole state once, then the playback events as they happen.
+/// Progress is sent on a timer instead, interpolated from the last update.
+async fn stream(mut socket: WebSocket, app_handle: tauri::AppHandle) {
+    let mut states = app_handle.state::<AppStore>().subscribe();
+    let mut prev = states.borrow_and_update().clone();
+    let mut updated_at = Instant::now();
+    let initial = serde_json::json!({ "type": "state", "payload": &prev });
+    if socket
+        .send(Message::Text(initial.to_string()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut progress_ticker = tokio::time::interval(STREAM_PROGRESS_INTERVAL);
+    loop {
+        tokio::select! {
+            changed = states.changed() => {
+                if changed.is_err() {
+                    break;
+                }
+                let next = states.borrow_and_update().clone();
+                for event in PlaybackEvent::diff(&prev, &next) {
+                    if matches!(event, PlaybackEvent::ProgressChanged(_)) {
+                        continue;
+                    }
+                    if socket.send(message(&event)).await.is_err() {
+                        return;
+                    }
+                }
+                prev = next;
+                updated_at = Instant::now();
+            }
+            _ = progress_ticker.tick() => {
+                if !prev.playing {
+                    continue;
+                }
+                let duration_ms = prev.curr.as_ref().map_or(u64::MAX, |curr| curr.duration_ms);
+                let progress_ms = (prev.progress_ms + updated_at.elapsed().as_millis() as u64)
+                    .min(duration_ms);
+                let event = PlaybackEvent::ProgressChanged(progress_ms);
+                if socket.send(message(&event)).await.is_err() {
+                    return;
+                }
+            }
+            received = socket.recv() => match received {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn open_stream(State(state): State<ApiState>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| stream(socket, state.app_handle))
+}
+
+/// Puts every route of `router` behind [`authorize`].
+fn protect(router: Router, auth: Auth) -> Router {
+    router.route_layer(middleware::from_fn_with_state(auth, authorize))
+}
+
+fn router(app_handle: tauri::AppHandle, token: String, allowed_origins: Vec<String>) -> Router {
+    let auth = Auth {
+        token: Arc::new(token),
+        allowed_origins: Arc::new(allowed_origins),
+    };
+    let router = Router::new()
+        .route("/state", get(get_state))
+        .route("/stream", get(open_stream))
+        .route("/:command", post(run_command))
+        .with_state(ApiState { app_handle });
+    protect(router, auth)
+}
+
+async fn serve(app_handle: tauri::AppHandle, settings: HttpApiSettings, token: String) {
+    let address = SocketAddr::new(settings.bind_address, settings.port);
+    if !settings.bind_address.is_loopback() {
+        tracing::warn!(%address, "http api reachable from the network, tokens are sent in the clear");
+    }
+    let server = match axum::Server::try_bind(&address) {
+        Ok(server) => server,
+        Err(error) => {
+            tracing::error!(%error, %address, "failed to bind http api");
+            return;
+        }
+    };
+
+    tracing::info!(%address, "http api listening");
+    let router = router(app_handle, token, settings.allowed_origins);
+    if let Err(error) = server.serve(router.into_make_service()).await {
+        tracing::error!(%error, "http api stopped");
+    }
+}
+
+/// Starts, stops or restarts the server to match `settings`.
+pub fn apply(app_handle: &tauri::AppHandle, settings: &HttpApiSettings) {
+    let http_api = app_handle.state::<HttpApi>();
+    let mut running = http_api.0.lock().unwrap();
+    if let Some((running_settings, _)) = running.as_ref() {
+        if running_settings == settings {
+            return;
+        }
+    }
+
+    if let Some((_, handle)) = running.take() {
+        handle.abort();
+        tracing::info!("http api stopped");
+    }
+    if !settings.enabled {
+        return;
+    }
+
+    let token = match load_or_create_token(app_handle) {
+        Ok(token) => token,
+        Err(error) => {
+            tracing::error!(%error, "failed to read http api token");
+            return;
+        }
+    };
+    let handle = tauri::async_runtime::spawn(serve(app_handle.clone(), settings.clone(), token));
+    *running = Some((settings.clone(), handle));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "secret-token";
+
+    fn to(uri: &str) -> axum::http::request::Builder {
+        Request::builder().uri(uri)
+    }
+
+    /// The real routes need a running app, so `/state` is stubbed. What is
+    /// under test is the layer in front of it.
+    async fn status(request: axum::http::request::Builder) -> StatusCode {
+        let auth = Auth {
+            token: Arc::new(TOKEN.into()),
+            allowed_origins: Arc::new(vec!["http://localhost:8080".into()]),
+        };
+        let router = protect(Router::new().route("/state", get(|| async { "{}" })), auth);
+        router
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(token_matches("abc", "abc"));
+        assert!(!token_matches("abd", "abc"));
+        assert!(!token_matches("abcd", "abc"));
+        assert!(!token_matches("", "abc"));
+    }
+
+    #[test]
+    fn reads_the_token_from_the_header_before_the_query() {
+        let request = to("/state?token=query")
+            .header(header::AUTHORIZATION, "Bearer header")
+            .body(())
+            .unwrap();
+        assert_eq!(given_token(&request).as_deref(), Some("header"));
+
+        let request = to("/stream?other=1&token=query").body(()).unwrap();
+        assert_eq!(given_token(&request).as_deref(), Some("query"));
+
+        let request = to("/state")
+            .header(header::AUTHORIZATION, "Basic header")
+            .body(())
+            .unwrap();
+        assert_eq!(given_token(&request), None);
+    }
+
+    #[tokio::test]
+    async fn rejects_a_missing_or_wrong_token() {
+        assert_eq!(status(to("/state")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(to("/state").header(header::AUTHORIZATION, "Bearer wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(to("/state?token=wrong")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn lets_the_right_token_through() {
+        let bearer = format!("Bearer {}", TOKEN);
+        assert_eq!(
+            status(to("/state").header(header::AUTHORIZATION, bearer)).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(to(&format!("/state?token={}", TOKEN))).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn lets_allowed_origins_through() {
+        let request =
+            to(&format!("/state?token={}", TOKEN)).header(header::ORIGIN, "http://localhost:8080");
+        assert_eq!(status(request).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn rejects_other_origins_even_with_the_token() {
+        let request =
+            to(&format!("/state?token={}", TOKEN)).header(header::ORIGIN, "https://example.com");
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+
+        let request = to("/state")
+            .header(header::ORIGIN, "https://example.com")
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN));
+        assert_eq!(status(request).await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn accepts_clients_without_an_origin() {
+        // What curl and other command line clients send.
+        let request = to("/state").header(header::AUTHORIZATION, format!("Bearer {}", TOKEN));
+        assert_eq!(status(request).await, StatusCode::OK);
+    }
+}
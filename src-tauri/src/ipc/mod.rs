@@ -0,0 +1,194 @@
+use fs2::FileExt;
+use interprocess::local_socket::{LocalSocketListener, LocalSocketStream};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::time::Duration;
+use tauri::Manager;
+
+use crate::deep_link;
+use crate::handlers::control::{self, ControlCommand};
+use crate::paths::AppPaths;
+use crate::state::AppStore;
+
+pub use spmp_ipc::protocol::*;
+
+/// How long a second launch waits for a primary that holds the lock but is
+/// still starting to answer on the socket.
+const FORWARD_ATTEMPTS: u32 = 20;
+const FORWARD_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// An exclusive lock on `<runtime dir>/spotify-mini-player.lock`, held for as
+/// long as the app runs. The OS releases it when the process dies, crashes
+/// included, so unlike the socket it can't be left behind stale.
+pub struct InstanceLock(#[allow(dead_code)] File);
+
+pub enum Instance {
+    /// This process runs the app. There is no lock if the lock file couldn't
+    /// be opened, and no listener if the socket couldn't be bound. The app
+    /// still runs then, just without taking over later launches.
+    Primary(Option<InstanceLock>, Option<LocalSocketListener>),
+    /// Another process runs the app and took over the arguments.
+    Secondary,
+}
+
+fn lock_path(paths: &AppPaths) -> std::path::PathBuf {
+    paths.runtime_dir.join("spotify-mini-player.lock")
+}
+
+fn try_lock(paths: &AppPaths) -> std::io::Result<Option<InstanceLock>> {
+    paths.create_runtime_dir()?;
+    let file = File::options()
+        .create(true)
+        .write(true)
+        .open(lock_path(paths))?;
+    match file.try_lock_exclusive() {
+        Ok(()) => Ok(Some(InstanceLock(file))),
+        Err(error) if error.kind() == fs2::lock_contended_error().kind() => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+fn forward(paths: &AppPaths, args: Vec<String>) {
+    let request = IpcRequest::Forward { args };
+    for attempt in 1..=FORWARD_ATTEMPTS {
+        match send(paths, &request) {
+            Ok(_) => return,
+            Err(error) if attempt == FORWARD_ATTEMPTS => {
+                tracing::error!(%error, "running instance didn't answer, dropping arguments")
+            }
+            Err(_) => std::thread::sleep(FORWARD_RETRY_DELAY),
+        }
+    }
+}
+
+/// Becomes the single running instance, unless one already runs, in which
+/// case `args` are handed over to it. The lock decides which process that
+/// is, so two launches at the same moment can't both win; the socket only
+/// carries the arguments.
+pub fn acquire(paths: &AppPaths, args: Vec<String>) -> Instance {
+    let lock = match try_lock(paths) {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            forward(paths, args);
+            return Instance::Secondary;
+        }
+        Err(error) => {
+            tracing::error!(%error, "failed to lock instance, running anyway");
+            return Instance::Primary(None, None);
+        }
+    };
+
+    // Only the lock holder gets here, so a socket file is left over from a
+    // crash.
+    #[cfg(unix)]
+    let _ = std::fs::remove_file(socket_name(paths));
+
+    match LocalSocketListener::bind(socket_name(paths)) {
+        Ok(listener) => {
+            // The runtime dir is private already, this guards against a
+            // `--home` or `XDG_RUNTIME_DIR` pointing somewhere shared.
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+
+                let permissions = std::fs::Permissions::from_mode(0o600);
+                if let Err(error) = std::fs::set_permissions(socket_name(paths), permissions) {
+                    tracing::warn!(%error, "failed to restrict instance socket");
+                }
+            }
+            Instance::Primary(Some(lock), Some(listener))
+        }
+        Err(error) => {
+            tracing::error!(%error, "failed to bind instance socket");
+            Instance::Primary(Some(lock), None)
+        }
+    }
+}
+
+fn to_rspotify(repeat_state: RepeatState) -> rspotify::model::RepeatState {
+    match repeat_state {
+        RepeatState::Off => rspotify::model::RepeatState::Off,
+        RepeatState::Track => rspotify::model::RepeatState::Track,
+        RepeatState::Context => rspotify::model::RepeatState::Context,
+    }
+}
+
+/// Runs playback requests through the same functions as the Tauri commands
+/// of the same name.
+async fn handle(app_handle: &tauri::AppHandle, request: IpcRequest) -> IpcResponse {
+    let result = match request {
+        IpcRequest::Forward { args } => {
+            deep_link::handle_forwarded(app_handle, &args).await;
+            Ok(())
+        }
+        IpcRequest::State => return IpcResponse::data(app_handle.state::<AppStore>().snapshot()),
+        IpcRequest::Control { command } => match ControlCommand::from_name(&command) {
+            Some(command) => control::execute(app_handle, command).await,
+            None => Err(format!("unknown command `{}`", command).into()),
+        },
+        IpcRequest::Seek { position_ms } => {
+            control::execute(app_handle, ControlCommand::Seek(position_ms)).await
+        }
+        IpcRequest::SeekBy { offset_ms } => {
+            control::execute(app_handle, ControlCommand::SeekBy(offset_ms)).await
+        }
+        IpcRequest::SetVolume { volume_percent } => {
+            control::execute(app_handle, ControlCommand::SetVolume(volume_percent)).await
+        }
+        IpcRequest::ChangeVolume { offset_percent } => {
+            control::execute(app_handle, ControlCommand::ChangeVolume(offset_percent)).await
+        }
+        IpcRequest::SetRepeatState { repeat_state } => {
+            let command = ControlCommand::SetRepeatState(to_rspotify(repeat_state));
+            control::execute(app_handle, command).await
+        }
+    };
+    match result {
+        Ok(()) => IpcResponse::ok(),
+        Err(error) => IpcResponse::error(error),
+    }
+}
+
+fn serve_connection(
+    app_handle: &tauri::AppHandle,
+    stream: LocalSocketStream,
+) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        let response = match serde_json::from_str::<IpcRequest>(&line) {
+            Ok(request) => {
+                tracing::debug!(?request, "ipc request");
+                tauri::async_runtime::block_on(handle(app_handle, request))
+            }
+            Err(error) => IpcResponse::error(error),
+        };
+        let mut response = serde_json::to_string(&response)?;
+        response.push('\n');
+        reader.get_mut().write_all(response.as_bytes())?;
+        line.clear();
+    }
+    Ok(())
+}
+
+/// Answers other processes on the instance socket, one thread per
+/// connection so a slow client can't block the others.
+pub fn serve(listener: LocalSocketListener, app_handle: tauri::AppHandle) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    tracing::warn!(%error, "failed to accept ipc connection");
+                    continue;
+                }
+            };
+            let app_handle = app_handle.clone();
+            std::thread::spawn(move || {
+                if let Err(error) = serve_connection(&app_handle, stream) {
+                    tracing::debug!(%error, "ipc connection closed");
+                }
+            });
+        }
+    });
+}
@@ -0,0 +1,125 @@
+use rspotify::model::RepeatState;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tauri::Manager;
+use tauri_plugin_store::{with_store, StoreCollection};
+
+use crate::covers;
+use crate::paths;
+use crate::state::{AppState, PlaybackEvent, SimplifiedItem};
+use crate::store_file;
+
+pub const STORE_LAST_PLAYBACK_KEY: &str = "last_playback";
+
+/// What the player showed when the app last saw playback, so a new window
+/// has something to show before the first poll returns.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LastPlayback {
+    pub item: SimplifiedItem,
+    pub progress_ms: u64,
+    pub shuffle: bool,
+    pub repeat_state: RepeatState,
+    pub cover_path: Option<PathBuf>,
+}
+
+impl LastPlayback {
+    fn new(state: &AppState, cover_path: Option<PathBuf>) -> Option<Self> {
+        Some(Self {
+            item: state.curr.clone()?,
+            progress_ms: state.progress_ms,
+            shuffle: state.shuffle,
+            repeat_state: state.repeat_state,
+            cover_path,
+        })
+    }
+
+    /// Shows the item as paused and stale, since it may have moved on since.
+    pub fn restore(self, state: &mut AppState) {
+        state.curr = Some(self.item);
+        state.progress_ms = self.progress_ms;
+        state.shuffle = self.shuffle;
+        state.repeat_state = self.repeat_state;
+        state.playing = false;
+        state.stale = true;
+        state.cover_path = self.cover_path.filter(|path| path.is_file());
+    }
+}
+
+pub fn load(app_handle: &tauri::AppHandle) -> Option<LastPlayback> {
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| Ok(store.cache.get(STORE_LAST_PLAYBACK_KEY).cloned()),
+    )
+    .ok()
+    .flatten()
+    .and_then(|value| serde_json::from_value(value).ok())
+}
+
+fn save(app_handle: &tauri::AppHandle, last_playback: &LastPlayback) {
+    let serialized = match serde_json::to_value(last_playback) {
+        Ok(serialized) => serialized,
+        Err(error) => {
+            tracing::warn!(%error, "failed to serialize last playback");
+            return;
+        }
+    };
+    let collection = app_handle.state::<StoreCollection>();
+    let result = with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| {
+            Ok(store
+                .cache
+                .insert(STORE_LAST_PLAYBACK_KEY.to_string(), serialized))
+        },
+    );
+    match result {
+        Ok(_) => store_file::schedule_save(app_handle),
+        Err(error) => tracing::warn!(%error, "failed to store last playback"),
+    }
+}
+
+/// Records the current state with whatever cover is already cached. Used on
+/// quit, so the restored progress is as recent as possible.
+pub fn save_state(app_handle: &tauri::AppHandle, state: &AppState) {
+    if state.stale {
+        return;
+    }
+    let cover_path = state
+        .curr
+        .as_ref()
+        .and_then(|item| covers::cached(app_handle, item));
+    if let Some(last_playback) = LastPlayback::new(state, cover_path) {
+        save(app_handle, &last_playback);
+    }
+}
+
+/// Records each new item once it's fresh, after caching its cover.
+pub fn on_state_change(app_handle: &tauri::AppHandle, state: &AppState, events: &[PlaybackEvent]) {
+    let item_changed = events.iter().any(|event| {
+        matches!(
+            event,
+            PlaybackEvent::TrackChanged(_) | PlaybackEvent::StaleChanged(false)
+        )
+    });
+    if !item_changed || state.stale || state.curr.is_none() {
+        return;
+    }
+
+    let app_handle = app_handle.clone();
+    let state = state.clone();
+    tauri::async_runtime::spawn(async move {
+        let cover_path = match &state.curr {
+            Some(item) => covers::fetch(&app_handle, item).await,
+            None => None,
+        };
+        if let Some(last_playback) = LastPlayback::new(&state, cover_path) {
+            save(&app_handle, &last_playback);
+        }
+    });
+}
@@ -3,164 +3,196 @@
     windows_subsystem = "windows"
 )]
 
-use std::sync::Arc;
-
-use cocoa::appkit::{NSWindow, NSWindowStyleMask, NSWindowTitleVisibility};
-use cocoa::base::id;
-use reauth::reauth_spotify;
-
 use rspotify::{AuthCodeSpotify, Credentials, OAuth};
 use scopes::get_scopes;
-use tauri_plugin_store::{with_store, PluginBuilder, StoreBuilder, StoreCollection};
+use tauri_plugin_store::{PluginBuilder, StoreBuilder};
 
 use std::sync::Mutex as SyncMutex;
-use tauri::async_runtime::Mutex;
-use tauri::{
-    ActivationPolicy, AppHandle, GlobalWindowEvent, Manager, RunEvent, SystemTray, SystemTrayEvent,
-    SystemTrayMenu, Window, WindowBuilder, WindowEvent,
-};
-use tauri_plugin_positioner::{Position, WindowExt};
-use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial, NSVisualEffectState};
+use tauri::{AppHandle, Manager, RunEvent, SystemTrayEvent};
 
+mod autostart;
+mod covers;
+mod deep_link;
+mod diagnostics;
+mod error_log;
 mod handlers;
 mod helpers;
+mod http_api;
+mod ipc;
+mod last_playback;
+#[cfg(target_os = "linux")]
+mod mpris;
+mod notifications;
+mod paths;
+mod platform;
 mod reauth;
 mod redirect_uri;
 mod scopes;
+mod settings;
+mod shortcuts;
+mod shutdown;
+mod spotify_request;
 mod state;
+mod store_file;
+mod store_schema;
+mod telemetry;
+mod tray;
+mod window;
 
+use error_log::ErrorLog;
 use handlers::*;
+use http_api::HttpApi;
+use notifications::Notifications;
+use paths::AppPaths;
+use reauth::ReauthLock;
+use settings::SettingsState;
+use shortcuts::Shortcuts;
 use state::*;
-
-fn create_tray_window(app_handle: &AppHandle) -> tauri::Result<Window> {
-    let window = WindowBuilder::new(
-        app_handle,
-        "main",
-        tauri::WindowUrl::App("index.html".into()),
-    )
-    .fullscreen(false)
-    .inner_size(200., 340.)
-    .resizable(false)
-    .title("spotify mini player")
-    .transparent(true)
-    .decorations(true)
-    .visible(false)
-    .always_on_top(true)
-    .build()
-    .unwrap();
-    window.move_window(Position::TrayBottomCenter).unwrap();
-
-    #[cfg(target_os = "macos")]
-    {
-        apply_vibrancy(
-            &window,
-            NSVisualEffectMaterial::Popover,
-            Some(NSVisualEffectState::Active),
-            Some(9.0),
-        )
-        .unwrap();
-
-        window
-            .with_webview(|webview| unsafe {
-                use NSWindowTitleVisibility::*;
-                let ns_window: id = webview.ns_window();
-                ns_window.setTitleVisibility_(NSWindowTitleHidden);
-                ns_window.setStyleMask_(NSWindowStyleMask::NSFullSizeContentViewWindowMask);
-            })
-            .unwrap();
-    }
-
-    window.show().unwrap();
-    Ok(window)
-}
+use tray::TrayTitle;
+use window::WindowSettings;
 
 fn handle_on_system_tray_event(app: &AppHandle, event: SystemTrayEvent) {
     tauri_plugin_positioner::on_tray_event(app, &event);
-    if let SystemTrayEvent::LeftClick { .. } = event {
-        match app.get_window("main") {
-            Some(window) => {
-                if let Ok(true) = window.is_visible() {
-                    window.close().unwrap();
-                    unsubscribe_to_event_loop(app).unwrap();
-                }
-            }
-            None => {
-                create_tray_window(app).unwrap();
-                subscribe_to_event_loop(app).unwrap();
-                let mutex_app = Box::new(Mutex::new(app.clone()));
-                tauri::async_runtime::spawn(async move {
-                    let app = mutex_app.lock().await;
-                    reauth_spotify(&app).await.expect("Reauth Error");
-                    let app_state = app.state::<AppStore>().0.clone();
-                    let mut app_state = app_state.lock().await;
-                    app_state.get_current_playback().await
-                });
-            }
-        }
-    }
-}
-
-fn handle_on_window_event(event: GlobalWindowEvent) {
-    use WindowEvent::*;
-    if let Focused(false) = event.event() {
-        #[cfg(not(debug_assertions))]
-        {
-            let window = event.window();
-            let app_handle = window.app_handle();
-            window.close().unwrap();
-            unsubscribe_to_event_loop(&app_handle).unwrap();
+    match event {
+        SystemTrayEvent::LeftClick { .. } => window::toggle_window(app),
+        SystemTrayEvent::MenuItemClick { id, .. } if id == tray::SHOW_PLAYER_ITEM => {
+            window::toggle_window(app)
         }
+        SystemTrayEvent::MenuItemClick { id, .. } => tray::on_menu_item_click(app, id),
+        _ => {}
     }
 }
 
 fn main() {
-    env_logger::init();
+    let context = tauri::generate_context!();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let paths =
+        AppPaths::resolve(&context.config().tauri.bundle.identifier, &args).expect("no app dirs");
+    let (log_level, log_guard) = telemetry::init(&paths.log_dir());
+
+    let (instance_lock, listener) = match ipc::acquire(&paths, args.clone()) {
+        ipc::Instance::Primary(instance_lock, listener) => (instance_lock, listener),
+        ipc::Instance::Secondary => {
+            tracing::info!("handed over to the running instance");
+            return;
+        }
+    };
 
     let creds = Credentials::from_env().unwrap();
     let scopes = get_scopes();
     let oauth = OAuth::from_env(scopes).unwrap();
 
-    let spotify = AuthCodeSpotify::new(creds, oauth);
+    let mut spotify = AuthCodeSpotify::new(creds, oauth);
 
-    let store = StoreBuilder::new(STORE_PATH_BUF.parse().unwrap()).build();
+    paths.move_legacy_store();
+    store_file::recover(&paths.store_path());
+    let store = StoreBuilder::new(paths.store_path()).build();
 
     tauri::Builder::default()
         .plugin(PluginBuilder::default().store(store).build())
-        .system_tray(SystemTray::new().with_menu(SystemTrayMenu::new()))
+        .system_tray(tray::build())
         .plugin(tauri_plugin_positioner::init())
         .on_system_tray_event(handle_on_system_tray_event)
-        .on_window_event(handle_on_window_event)
-        .setup(|app| {
-            app.set_activation_policy(ActivationPolicy::Accessory);
+        .on_window_event(window::on_window_event)
+        .setup(move |app| {
+            platform::init(app);
+            let app_handle = app.handle();
+            // Lets the player show cached covers, wherever the cache dir is.
+            let covers_dir = app_handle.state::<AppPaths>().covers_dir();
+            if let Err(error) = app
+                .asset_protocol_scope()
+                .allow_directory(&covers_dir, false)
+            {
+                tracing::warn!(%error, "failed to allow the covers dir");
+            }
+            app.manage(store_file::spawn_writer(&app_handle));
+            if let Err(error) = store_schema::migrate(&app_handle) {
+                tracing::error!(%error, "failed to migrate store");
+            }
+            let settings = settings::load(&app_handle);
+            spotify.oauth.redirect_uri =
+                redirect_uri::with_port(&spotify.oauth.redirect_uri, settings.redirect_uri_port);
+            settings::apply(&app_handle, &settings);
+            app.manage(SettingsState(SyncMutex::new(settings)));
+            let window_options = window::load_options(&app_handle);
+            tray::update_window_mode(&app_handle, window_options.mode);
+            app.manage(WindowSettings(SyncMutex::new(window_options)));
+            let tray_title_options = tray::load_title_options(&app_handle);
+            app.manage(TrayTitle(SyncMutex::new(tray_title_options)));
+            let notification_options = notifications::load_options(&app_handle);
+            app.manage(Notifications(SyncMutex::new(notification_options)));
+            app.manage(AppStore::spawn(&app_handle, spotify));
+            shortcuts::register(&app_handle, shortcuts::load_bindings(&app_handle));
+            #[cfg(target_os = "linux")]
+            tauri::async_runtime::spawn(mpris::serve(app_handle.clone()));
+
+            if autostart::launched_at_login() {
+                tracing::info!("launched at login");
+            }
+            tauri::async_runtime::spawn(autostart::reauth_on_launch(app_handle.clone()));
+
+            if let Some(listener) = listener {
+                ipc::serve(listener, app_handle.clone());
+            }
+            deep_link::register(&app_handle);
+            tauri::async_runtime::spawn(async move {
+                deep_link::handle_links(&app_handle, &args).await;
+            });
             Ok(())
         })
         .manage(EventLoopHandle(SyncMutex::new(None)))
-        .manage(AppStore(Arc::new(Mutex::new(AppState {
-            spotify_client: Arc::new(Mutex::new(spotify)),
-            ..AppState::default()
-        }))))
+        .manage(ErrorLog::default())
+        .manage(HttpApi::default())
+        .manage(Shortcuts::default())
+        .manage(ReauthLock::default())
+        .manage(log_level)
+        .manage(log_guard)
+        .manage(paths)
+        .manage(instance_lock)
         .invoke_handler(tauri::generate_handler![
             login_spotify,
+            get_app_state,
             next_track,
             prev_track,
             play_pause,
             toggle_saved,
             toggle_shuffle,
-            cycle_repeat_state
+            cycle_repeat_state,
+            get_recent_errors,
+            get_log_level,
+            set_log_level,
+            get_diagnostics,
+            write_diagnostics_report,
+            get_tray_title_options,
+            set_tray_title_options,
+            seek,
+            get_shortcuts,
+            set_shortcuts,
+            get_notification_options,
+            set_notification_options,
+            get_window_mode,
+            set_window_mode,
+            get_window_layout,
+            set_window_layout,
+            get_queue,
+            get_devices,
+            transfer_playback,
+            quit,
+            get_autostart,
+            enable_autostart,
+            disable_autostart,
+            get_settings,
+            update_settings,
+            get_http_api_token
         ])
-        .build(tauri::generate_context!())
+        .build(context)
         .expect("error while building tauri application")
         .run(|app_handle, event| {
             if let RunEvent::ExitRequested { api, .. } = event {
-                let collection = app_handle.state::<StoreCollection>();
-                with_store(
-                    app_handle,
-                    collection,
-                    STORE_PATH_BUF.parse().unwrap(),
-                    |store| store.save(app_handle),
-                )
-                .unwrap();
-                api.prevent_exit();
+                shutdown::save_store(app_handle);
+                if !shutdown::is_quitting() {
+                    api.prevent_exit();
+                }
             }
         });
 }
@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use async_trait::async_trait;
+use rspotify::model::RepeatState;
+use rspotify::prelude::Id;
+use tauri::Manager;
+use zbus::zvariant::{ObjectPath, Value};
+use zbus::{dbus_interface, fdo, Connection, ConnectionBuilder, SignalContext};
+
+use crate::handlers::control::{self, ControlCommand};
+use crate::handlers::HandlerError;
+use crate::state::{AppState, AppStore, PlayableId, PlaybackEvent, SimplifiedItem};
+
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.spotify_mini_player";
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// Progress jumps larger than this, beyond what playback itself explains, are
+/// reported to clients as a `Seeked` signal.
+const SEEK_TOLERANCE_MS: i64 = 1500;
+
+fn playback_status(state: &AppState) -> &'static str {
+    match (&state.curr, state.playing) {
+        (None, _) => "Stopped",
+        (Some(_), true) => "Playing",
+        (Some(_), false) => "Paused",
+    }
+}
+
+fn loop_status(repeat_state: RepeatState) -> &'static str {
+    match repeat_state {
+        RepeatState::Off => "None",
+        RepeatState::Track => "Track",
+        RepeatState::Context => "Playlist",
+    }
+}
+
+fn track_path(curr: Option<&SimplifiedItem>) -> ObjectPath<'static> {
+    let path = match curr.and_then(|c| c.id.as_ref()) {
+        Some(PlayableId::Track(id)) => format!("{}/Track/{}", OBJECT_PATH, id.id()),
+        Some(PlayableId::Episode(id)) => format!("{}/Episode/{}", OBJECT_PATH, id.id()),
+        None => NO_TRACK.into(),
+    };
+    ObjectPath::try_from(path).unwrap_or_else(|_| ObjectPath::from_static_str_unchecked(NO_TRACK))
+}
+
+fn metadata(curr: Option<&SimplifiedItem>) -> HashMap<String, Value<'static>> {
+    let mut metadata = HashMap::new();
+    metadata.insert("mpris:trackid".into(), Value::from(track_path(curr)));
+
+    let curr = match curr {
+        Some(curr) => curr,
+        None => return metadata,
+    };
+
+    metadata.insert(
+        "mpris:length".into(),
+        Value::from(curr.duration_ms as i64 * 1000),
+    );
+    if let Some(name) = &curr.name {
+        metadata.insert("xesam:title".into(), Value::from(name.clone()));
+    }
+    if !curr.artists.is_empty() {
+        let artists: Vec<String> = curr.artists.iter().map(|a| a.name.clone()).collect();
+        metadata.insert("xesam:artist".into(), Value::from(artists));
+    }
+    if let Some(album) = &curr.album {
+        metadata.insert("xesam:album".into(), Value::from(album.name.clone()));
+    }
+    if let Some(image_url) = &curr.image_url {
+        metadata.insert("mpris:artUrl".into(), Value::from(image_url.clone()));
+    }
+    let uri = match &curr.id {
+        Some(PlayableId::Track(id)) => Some(id.uri()),
+        Some(PlayableId::Episode(id)) => Some(id.uri()),
+        None => None,
+    };
+    if let Some(uri) = uri {
+        metadata.insert("xesam:url".into(), Value::from(uri));
+    }
+    metadata
+}
+
+/// The `org.mpris.MediaPlayer2` root interface. The popup can't be raised on
+/// its own and quitting goes through the tray, so both are advertised as
+/// unsupported.
+struct MediaPlayer2;
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2")]
+impl MediaPlayer2 {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[dbus_interface(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[dbus_interface(property)]
+    fn identity(&self) -> &str {
+        "Spotify Mini Player"
+    }
+
+    #[dbus_interface(property)]
+    fn desktop_entry(&self) -> &str {
+        "spotify-mini-player"
+    }
+
+    #[dbus_interface(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[dbus_interface(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// Where the player reads playback from and sends commands to. The app is the
+/// only one outside of tests.
+#[async_trait]
+trait Backend: Send + Sync {
+    fn snapshot(&self) -> AppState;
+
+    async fn run(&self, command: ControlCommand) -> Result<(), HandlerError>;
+}
+
+/// Runs commands the same way as the tray and the popup.
+struct AppBackend(tauri::AppHandle);
+
+#[async_trait]
+impl Backend for AppBackend {
+    fn snapshot(&self) -> AppState {
+        self.0.state::<AppStore>().snapshot()
+    }
+
+    async fn run(&self, command: ControlCommand) -> Result<(), HandlerError> {
+        control::execute(&self.0, command).await
+    }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface, backed by [`AppState`].
+struct Player {
+    backend: Arc<dyn Backend>,
+}
+
+impl Player {
+    fn snapshot(&self) -> AppState {
+        self.backend.snapshot()
+    }
+
+    async fn run(&self, command: ControlCommand) -> fdo::Result<()> {
+        self.backend
+            .run(command)
+            .await
+            .map_err(|error| fdo::Error::Failed(error.to_string()))
+    }
+}
+
+#[dbus_interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    async fn next(&self) -> fdo::Result<()> {
+        self.run(ControlCommand::NextTrack).await
+    }
+
+    async fn previous(&self) -> fdo::Result<()> {
+        self.run(ControlCommand::PrevTrack).await
+    }
+
+    async fn play_pause(&self) -> fdo::Result<()> {
+        self.run(ControlCommand::PlayPause).await
+    }
+
+    async fn play(&self) -> fdo::Result<()> {
+        if self.snapshot().playing {
+            return Ok(());
+        }
+        self.play_pause().await
+    }
+
+    async fn pause(&self) -> fdo::Result<()> {
+        if !self.snapshot().playing {
+            return Ok(());
+        }
+        self.play_pause().await
+    }
+
+    async fn stop(&self) -> fdo::Result<()> {
+        self.pause().await
+    }
+
+    /// `offset` is in microseconds, like every position in MPRIS.
+    async fn seek(&self, offset: i64) -> fdo::Result<()> {
+        self.run(ControlCommand::SeekBy(offset / 1000)).await
+    }
+
+    async fn set_position(&self, track_id: ObjectPath<'_>, position: i64) -> fdo::Result<()> {
+        let state = self.snapshot();
+        let duration_us = state
+            .curr
+            .as_ref()
+            .map_or(0, |c| c.duration_ms as i64 * 1000);
+        // The spec asks to ignore stale track ids and out of range positions.
+        if track_id != track_path(state.curr.as_ref()) || !(0..=duration_us).contains(&position) {
+            return Ok(());
+        }
+
+        self.run(ControlCommand::Seek(position as u64 / 1000)).await
+    }
+
+    fn open_uri(&self, _uri: &str) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported("OpenUri is not supported".into()))
+    }
+
+    #[dbus_interface(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[dbus_interface(property)]
+    fn playback_status(&self) -> &str {
+        playback_status(&self.snapshot())
+    }
+
+    #[dbus_interface(property)]
+    fn loop_status(&self) -> &str {
+        loop_status(self.snapshot().repeat_state)
+    }
+
+    #[dbus_interface(property)]
+    async fn set_loop_status(&self, value: &str) {
+        let repeat_state = match value {
+            "None" => RepeatState::Off,
+            "Track" => RepeatState::Track,
+            "Playlist" => RepeatState::Context,
+            _ => return,
+        };
+        let _ = self.run(ControlCommand::SetRepeatState(repeat_state)).await;
+    }
+
+    #[dbus_interface(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[dbus_interface(property)]
+    fn shuffle(&self) -> bool {
+        self.snapshot().shuffle
+    }
+
+    #[dbus_interface(property)]
+    async fn set_shuffle(&self, value: bool) {
+        if self.snapshot().shuffle == value {
+            return;
+        }
+        let _ = self.run(ControlCommand::ToggleShuffle).await;
+    }
+
+    #[dbus_interface(property)]
+    fn metadata(&self) -> HashMap<String, Value<'static>> {
+        metadata(self.snapshot().curr.as_ref())
+    }
+
+    #[dbus_interface(property)]
+    fn volume(&self) -> f64 {
+        self.snapshot().volume_percent.unwrap_or_default() as f64 / 100.0
+    }
+
+    #[dbus_interface(property)]
+    async fn set_volume(&self, value: f64) {
+        let volume_percent = (value.clamp(0.0, 1.0) * 100.0).round() as u8;
+        let _ = self.run(ControlCommand::SetVolume(volume_percent)).await;
+    }
+
+    /// Clients poll this instead of listening for changes, so it never emits.
+    #[dbus_interface(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.snapshot().progress_ms as i64 * 1000
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[dbus_interface(property)]
+    fn can_play(&self) -> bool {
+        self.snapshot().curr.is_some()
+    }
+
+    #[dbus_interface(property)]
+    fn can_pause(&self) -> bool {
+        self.snapshot().curr.is_some()
+    }
+
+    #[dbus_interface(property)]
+    fn can_seek(&self) -> bool {
+        self.snapshot().curr.map_or(false, |c| c.duration_ms > 0)
+    }
+
+    #[dbus_interface(property)]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// Tracks where playback should be if nobody touched it, to tell seeks
+/// apart from regular progress updates.
+struct SeekDetector {
+    progress_ms: u64,
+    at: Instant,
+}
+
+impl SeekDetector {
+    fn new(progress_ms: u64) -> Self {
+        Self {
+            progress_ms,
+            at: Instant::now(),
+        }
+    }
+
+    fn is_seek(&mut self, prev: &AppState, progress_ms: u64) -> bool {
+        let elapsed_ms = match prev.playing {
+            true => self.at.elapsed().as_millis() as i64,
+            false => 0,
+        };
+        let expected_ms = self.progress_ms as i64 + elapsed_ms;
+        *self = Self::new(progress_ms);
+        (progress_ms as i64 - expected_ms).abs() > SEEK_TOLERANCE_MS
+    }
+}
+
+async fn emit_changes(
+    player: &Player,
+    ctxt: &SignalContext<'_>,
+    prev: &AppState,
+    next: &AppState,
+    seek_detector: &mut SeekDetector,
+) -> zbus::Result<()> {
+    use PlaybackEvent::*;
+    for event in PlaybackEvent::diff(prev, next) {
+        match event {
+            TrackChanged(_) => {
+                *seek_detector = SeekDetector::new(next.progress_ms);
+                player.metadata_changed(ctxt).await?;
+                player.playback_status_changed(ctxt).await?;
+                player.can_play_changed(ctxt).await?;
+                player.can_pause_changed(ctxt).await?;
+                player.can_seek_changed(ctxt).await?;
+            }
+            PlaybackPaused(progress_ms) | PlaybackResumed(progress_ms) => {
+                *seek_detector = SeekDetector::new(progress_ms);
+                player.playback_status_changed(ctxt).await?;
+            }
+            ProgressChanged(progress_ms) => {
+                if seek_detector.is_seek(prev, progress_ms) {
+                    Player::seeked(ctxt, progress_ms as i64 * 1000).await?;
+                }
+            }
+            ShuffleChanged(_) => player.shuffle_changed(ctxt).await?,
+            RepeatChanged(_) => player.loop_status_changed(ctxt).await?,
+            DeviceChanged(_) | SavedChanged(_) | StaleChanged(_) => {}
+        }
+    }
+
+    if prev.volume_percent != next.volume_percent {
+        player.volume_changed(ctxt).await?;
+    }
+    Ok(())
+}
+
+/// Serves both interfaces as `bus_name` on the session bus.
+async fn connect(bus_name: &str, backend: Arc<dyn Backend>) -> zbus::Result<Connection> {
+    ConnectionBuilder::session()?
+        .name(bus_name)?
+        .serve_at(OBJECT_PATH, MediaPlayer2)?
+        .serve_at(OBJECT_PATH, Player { backend })?
+        .build()
+        .await
+}
+
+async fn run(app_handle: tauri::AppHandle) -> zbus::Result<()> {
+    let connection = connect(BUS_NAME, Arc::new(AppBackend(app_handle.clone()))).await?;
+    tracing::info!(bus_name = BUS_NAME, "mpris server started");
+
+    let player_ref = connection
+        .object_server()
+        .interface::<_, Player>(OBJECT_PATH)
+        .await?;
+
+    let mut snapshot = app_handle.state::<AppStore>().subscribe();
+    let mut prev = snapshot.borrow().clone();
+    let mut seek_detector = SeekDetector::new(prev.progress_ms);
+
+    while snapshot.changed().await.is_ok() {
+        let next = snapshot.borrow().clone();
+        let player = player_ref.get().await;
+        let ctxt = player_ref.signal_context();
+        if let Err(error) = emit_changes(&player, ctxt, &prev, &next, &mut seek_detector).await {
+            tracing::warn!(%error, "failed to emit mpris change");
+        }
+        prev = next;
+    }
+    Ok(())
+}
+
+/// Exposes the player on the session bus until the app exits. Uses
+/// `DBUS_SESSION_BUS_ADDRESS`, so it can be pointed at a private bus started
+/// with `dbus-run-session` to try it with `playerctl`.
+pub async fn serve(app_handle: tauri::AppHandle) {
+    if let Err(error) = run(app_handle).await {
+        tracing::warn!(%error, "mpris server unavailable");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rspotify::model::TrackId;
+    use std::sync::Mutex as SyncMutex;
+    use std::time::Duration;
+    use zbus::zvariant::OwnedValue;
+
+    fn item() -> SimplifiedItem {
+        SimplifiedItem {
+            id: Some(PlayableId::Track(
+                TrackId::from_id("4iV5W9uYEdYUVa79Axb7Rh")
+                    .unwrap()
+                    .into_static(),
+            )),
+            name: Some("Song".into()),
+            duration_ms: 200_000,
+            ..SimplifiedItem::default()
+        }
+    }
+
+    fn detector(progress_ms: u64, ago: Duration) -> SeekDetector {
+        SeekDetector {
+            progress_ms,
+            at: Instant::now() - ago,
+        }
+    }
+
+    fn playing(playing: bool) -> AppState {
+        AppState {
+            playing,
+            ..AppState::default()
+        }
+    }
+
+    #[test]
+    fn progress_while_playing_is_not_a_seek() {
+        let mut seek_detector = detector(10_000, Duration::from_secs(5));
+        assert!(!seek_detector.is_seek(&playing(true), 15_000));
+    }
+
+    #[test]
+    fn jumps_while_playing_are_seeks() {
+        let mut seek_detector = detector(10_000, Duration::from_secs(5));
+        assert!(seek_detector.is_seek(&playing(true), 60_000));
+        let mut seek_detector = detector(10_000, Duration::from_secs(5));
+        assert!(seek_detector.is_seek(&playing(true), 0));
+    }
+
+    #[test]
+    fn paused_progress_doesnt_advance() {
+        let mut seek_detector = detector(10_000, Duration::from_secs(5));
+        assert!(!seek_detector.is_seek(&playing(false), 10_000));
+        let mut seek_detector = detector(10_000, Duration::from_secs(5));
+        assert!(seek_detector.is_seek(&playing(false), 15_000));
+    }
+
+    #[test]
+    fn detector_restarts_from_the_last_progress() {
+        let mut seek_detector = detector(10_000, Duration::from_secs(5));
+        assert!(seek_detector.is_seek(&playing(false), 90_000));
+        assert!(!seek_detector.is_seek(&playing(false), 90_000));
+    }
+
+    struct FakeBackend {
+        state: AppState,
+        commands: SyncMutex<Vec<ControlCommand>>,
+    }
+
+    #[async_trait]
+    impl Backend for FakeBackend {
+        fn snapshot(&self) -> AppState {
+            self.state.clone()
+        }
+
+        async fn run(&self, command: ControlCommand) -> Result<(), HandlerError> {
+            self.commands.lock().unwrap().push(command);
+            Ok(())
+        }
+    }
+
+    /// Needs a session bus, run it with `dbus-run-session -- cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn serves_the_player_on_the_session_bus() {
+        let backend = Arc::new(FakeBackend {
+            state: AppState {
+                curr: Some(item()),
+                playing: true,
+                ..AppState::default()
+            },
+            commands: SyncMutex::new(Vec::new()),
+        });
+        let bus_name = format!("{}.test{}", BUS_NAME, std::process::id());
+        let _server = connect(&bus_name, backend.clone()).await.unwrap();
+
+        let client = Connection::session().await.unwrap();
+        let player = zbus::Proxy::new(
+            &client,
+            bus_name.as_str(),
+            OBJECT_PATH,
+            "org.mpris.MediaPlayer2.Player",
+        )
+        .await
+        .unwrap();
+
+        let status: String = player.get_property("PlaybackStatus").await.unwrap();
+        assert_eq!(status, "Playing");
+
+        let metadata: HashMap<String, OwnedValue> = player.get_property("Metadata").await.unwrap();
+        let title = String::try_from(metadata["xesam:title"].clone()).unwrap();
+        assert_eq!(title, "Song");
+        let length = i64::try_from(metadata["mpris:length"].clone()).unwrap();
+        assert_eq!(length, 200_000_000);
+
+        let () = player.call("PlayPause", &()).await.unwrap();
+        assert_eq!(
+            *backend.commands.lock().unwrap(),
+            [ControlCommand::PlayPause]
+        );
+    }
+}
@@ -0,0 +1,199 @@
+use notify_rust::Notification;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex as SyncMutex;
+use tauri::Manager;
+use tauri_plugin_store::{with_store, StoreCollection};
+
+use crate::covers;
+use crate::handlers::window_open;
+use crate::paths;
+use crate::state::{AppState, PlayableId, PlaybackEvent, SimplifiedItem};
+
+pub const STORE_NOTIFICATIONS_KEY: &str = "notifications";
+
+#[cfg(target_os = "linux")]
+const LIKE_ACTION: &str = "like";
+
+/// The notification whose Like action is being waited for. It is closed when
+/// the next one is shown, which ends the wait and frees its thread.
+#[cfg(target_os = "linux")]
+static WAITING: SyncMutex<Option<u32>> = SyncMutex::new(None);
+
+/// Which kinds of items get a notification when they start playing.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NotificationOptions {
+    pub tracks: bool,
+    pub episodes: bool,
+}
+
+impl Default for NotificationOptions {
+    fn default() -> Self {
+        Self {
+            tracks: true,
+            episodes: false,
+        }
+    }
+}
+
+impl NotificationOptions {
+    fn allows(&self, id: &PlayableId) -> bool {
+        match id {
+            PlayableId::Track(_) => self.tracks,
+            PlayableId::Episode(_) => self.episodes,
+        }
+    }
+}
+
+pub struct Notifications(pub SyncMutex<NotificationOptions>);
+
+pub fn load_options(app_handle: &tauri::AppHandle) -> NotificationOptions {
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| Ok(store.cache.get(STORE_NOTIFICATIONS_KEY).cloned()),
+    )
+    .ok()
+    .flatten()
+    .and_then(|value| serde_json::from_value(value).ok())
+    .unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+async fn close_waiting() {
+    let id = match WAITING.lock().unwrap().take() {
+        Some(id) => id,
+        None => return,
+    };
+    let result = async {
+        zbus::Connection::session()
+            .await?
+            .call_method(
+                Some("org.freedesktop.Notifications"),
+                "/org/freedesktop/Notifications",
+                Some("org.freedesktop.Notifications"),
+                "CloseNotification",
+                &(id,),
+            )
+            .await?;
+        zbus::Result::Ok(())
+    }
+    .await;
+    if let Err(error) = result {
+        tracing::debug!(%error, "failed to close previous notification");
+    }
+}
+
+async fn notify(app_handle: tauri::AppHandle, item: SimplifiedItem) {
+    #[cfg(target_os = "linux")]
+    close_waiting().await;
+
+    let cover_path = covers::fetch(&app_handle, &item).await;
+
+    let artists: Vec<&str> = item.artists.iter().map(|a| a.name.as_str()).collect();
+    let mut notification = Notification::new();
+    notification
+        .appname("Spotify Mini Player")
+        .summary(item.name.as_deref().unwrap_or("Unknown"))
+        .body(&artists.join(", "));
+    #[cfg(not(target_os = "macos"))]
+    if let Some(cover_path) = &cover_path {
+        notification.image_path(&cover_path.to_string_lossy());
+    }
+    #[cfg(target_os = "macos")]
+    let _ = cover_path;
+
+    // Showing can block on the notification server, and waiting for an
+    // action blocks until the notification is closed.
+    let result = tauri::async_runtime::spawn_blocking(move || {
+        #[cfg(target_os = "linux")]
+        let like_action = matches!(item.id, Some(PlayableId::Track(_)))
+            && notify_rust::get_capabilities().map_or(false, |capabilities| {
+                capabilities.iter().any(|c| c == "actions")
+            });
+        #[cfg(target_os = "linux")]
+        if like_action {
+            notification.action(LIKE_ACTION, "Like");
+        }
+
+        let handle = notification.show()?;
+        #[cfg(target_os = "linux")]
+        if like_action {
+            let id = handle.id();
+            *WAITING.lock().unwrap() = Some(id);
+            handle.wait_for_action(|action| {
+                if action == LIKE_ACTION {
+                    tauri::async_runtime::spawn(like(app_handle, item.id));
+                }
+            });
+            let mut waiting = WAITING.lock().unwrap();
+            if *waiting == Some(id) {
+                *waiting = None;
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = (handle, app_handle, item);
+        anyhow::Ok(())
+    })
+    .await;
+
+    if let Ok(Err(error)) = result {
+        tracing::warn!(%error, "failed to show notification");
+    }
+}
+
+/// Saves the notified track, unless it is no longer playing or was already
+/// saved in the meantime.
+#[cfg(target_os = "linux")]
+async fn like(app_handle: tauri::AppHandle, id: Option<PlayableId>) {
+    use crate::error_log::report;
+    use crate::handlers::playback;
+    use crate::reauth::ensure_token;
+    use crate::state::AppStore;
+
+    ensure_token(&app_handle).await;
+    let app_store = app_handle.state::<AppStore>();
+    let app_state = app_store.snapshot();
+    let curr = match app_state.curr {
+        Some(curr) if curr.id == id && !curr.saved => curr,
+        _ => return,
+    };
+    tracing::debug!(name = ?curr.name, "liked from notification");
+    let _ = report(
+        &app_handle,
+        "notification_like",
+        playback::toggle_saved(&app_store).await,
+    );
+}
+
+/// Notifies about the new item when the track changes, unless the popup is
+/// open and already shows it.
+pub fn on_state_change(app_handle: &tauri::AppHandle, state: &AppState, events: &[PlaybackEvent]) {
+    if !events
+        .iter()
+        .any(|event| matches!(event, PlaybackEvent::TrackChanged(_)))
+    {
+        return;
+    }
+    if window_open(app_handle) {
+        return;
+    }
+
+    let item = match &state.curr {
+        Some(item) => item,
+        None => return,
+    };
+    let options = app_handle
+        .state::<Notifications>()
+        .0
+        .lock()
+        .unwrap()
+        .clone();
+    if !item.id.as_ref().map_or(false, |id| options.allows(id)) {
+        return;
+    }
+
+    tauri::async_runtime::spawn(notify(app_handle.clone(), item.clone()));
+}
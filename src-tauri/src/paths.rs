@@ -0,0 +1,9 @@
+use std::path::PathBuf;
+use tauri::Manager;
+
+pub use spmp_ipc::paths::*;
+
+/// The store's path, which is also its key in the store plugin.
+pub fn store_path(app_handle: &tauri::AppHandle) -> PathBuf {
+    app_handle.state::<AppPaths>().store_path()
+}
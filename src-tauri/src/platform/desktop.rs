@@ -0,0 +1,26 @@
+use tauri::{Runtime, Window, WindowBuilder};
+use tauri_plugin_positioner::Position;
+
+/// Linux trays never report where the icon is, so the popup opens in the
+/// corner where most panels keep it.
+#[cfg(target_os = "linux")]
+pub const WINDOW_POSITION: Position = Position::TopRight;
+#[cfg(not(target_os = "linux"))]
+pub const WINDOW_POSITION: Position = Position::TrayBottomCenter;
+
+/// Linux and Windows have no tray-only app mode; the popup just stays out of
+/// the taskbar instead.
+pub fn init(_app: &mut tauri::App) {}
+
+/// There is no vibrancy outside macOS, so the popup is a plain transparent,
+/// undecorated window and the frontend draws its own background.
+pub fn window_builder<R: Runtime>(builder: WindowBuilder<'_, R>) -> WindowBuilder<'_, R> {
+    builder
+        .transparent(true)
+        .decorations(false)
+        .skip_taskbar(true)
+}
+
+pub fn style_window<R: Runtime>(_window: &Window<R>) -> tauri::Result<()> {
+    Ok(())
+}
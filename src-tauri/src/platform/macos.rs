@@ -0,0 +1,37 @@
+use cocoa::appkit::{NSWindow, NSWindowStyleMask, NSWindowTitleVisibility};
+use cocoa::base::id;
+use tauri::{ActivationPolicy, Runtime, Window, WindowBuilder};
+use tauri_plugin_positioner::Position;
+use window_vibrancy::{apply_vibrancy, NSVisualEffectMaterial, NSVisualEffectState};
+
+pub const WINDOW_POSITION: Position = Position::TrayBottomCenter;
+
+/// Keeps the app out of the dock and the app switcher; it lives in the tray.
+pub fn init(app: &mut tauri::App) {
+    app.set_activation_policy(ActivationPolicy::Accessory);
+}
+
+/// The title bar is hidden after the window is built, see [`style_window`].
+pub fn window_builder<R: Runtime>(builder: WindowBuilder<'_, R>) -> WindowBuilder<'_, R> {
+    builder.transparent(true).decorations(true)
+}
+
+/// Gives the popup the native popover background and hides its title bar
+/// while keeping the rounded corners of a decorated window.
+pub fn style_window<R: Runtime>(window: &Window<R>) -> tauri::Result<()> {
+    if let Err(error) = apply_vibrancy(
+        window,
+        NSVisualEffectMaterial::Popover,
+        Some(NSVisualEffectState::Active),
+        Some(9.0),
+    ) {
+        tracing::warn!(%error, "failed to apply vibrancy");
+    }
+
+    window.with_webview(|webview| unsafe {
+        use NSWindowTitleVisibility::*;
+        let ns_window: id = webview.ns_window();
+        ns_window.setTitleVisibility_(NSWindowTitleHidden);
+        ns_window.setStyleMask_(NSWindowStyleMask::NSFullSizeContentViewWindowMask);
+    })
+}
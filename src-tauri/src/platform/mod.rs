@@ -0,0 +1,12 @@
+//! Window and app setup that differs between operating systems. Everything
+//! outside this module is expected to build on every target.
+
+#[cfg(target_os = "macos")]
+mod macos;
+#[cfg(target_os = "macos")]
+pub use self::macos::*;
+
+#[cfg(not(target_os = "macos"))]
+mod desktop;
+#[cfg(not(target_os = "macos"))]
+pub use self::desktop::*;
@@ -4,8 +4,11 @@ use tauri_plugin_store::{with_store, StoreCollection};
 use thiserror::Error;
 
 use crate::{
+    paths,
     scopes::get_scopes,
-    state::{AppStore, STORE_PATH_BUF, STORE_TOKEN_KEY},
+    spotify_request,
+    state::{AppStore, STORE_TOKEN_KEY},
+    store_file,
 };
 
 #[derive(Debug, Error)]
@@ -14,13 +17,26 @@ enum ReauthError {
     TokenError(String),
 }
 
+/// Held for a whole refresh. The client holds a token without an access
+/// token while one is in flight, so nothing else may look at it or start a
+/// second refresh with a refresh token that is about to be replaced.
+#[derive(Default)]
+pub struct ReauthLock(tokio::sync::Mutex<()>);
+
+#[tracing::instrument(skip_all)]
 pub async fn reauth_spotify(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+    let reauth_lock = app_handle.state::<ReauthLock>();
+    let _guard = reauth_lock.0.lock().await;
+    refresh(app_handle).await
+}
+
+async fn refresh(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
     let collection = app_handle.state::<StoreCollection>();
 
     let token_store = with_store(
         app_handle,
         collection.clone(),
-        STORE_PATH_BUF.parse().unwrap(),
+        paths::store_path(app_handle),
         |store| Ok(store.cache.get(&STORE_TOKEN_KEY.to_string()).cloned()),
     )
     .map_err(|o| ReauthError::TokenError(o.to_string()))?;
@@ -39,18 +55,17 @@ pub async fn reauth_spotify(app_handle: &tauri::AppHandle) -> anyhow::Result<()>
             ..Token::default()
         })?;
 
-    let app_state = app_handle.state::<AppStore>().0.clone();
-    let app_state = app_state.lock().await;
-    let spotify_client = app_state.spotify_client.lock().await;
+    let app_store = app_handle.state::<AppStore>();
+    let spotify_client = &app_store.spotify_client;
     *spotify_client.token.lock().await.unwrap() = Some(token);
-    spotify_client.refresh_token().await?;
+    spotify_request::send("refresh_token", move || spotify_client.refresh_token()).await?;
 
     let token = spotify_client.token.lock().await.unwrap();
     let serialized_token = serde_json::to_value(token.as_ref().unwrap()).unwrap();
     with_store(
         app_handle,
         collection,
-        STORE_PATH_BUF.parse().unwrap(),
+        paths::store_path(app_handle),
         |store| {
             Ok(store
                 .cache
@@ -58,5 +73,34 @@ pub async fn reauth_spotify(app_handle: &tauri::AppHandle) -> anyhow::Result<()>
         },
     )
     .map_err(|o| ReauthError::TokenError(o.to_string()))?;
+    // The old refresh token may already be revoked, so losing this one to a
+    // crash would log the user out.
+    store_file::save_now(app_handle)?;
     Ok(())
 }
+
+/// Restores the session from the stored refresh token, unless the client
+/// already has a usable access token. Used by entry points that can run
+/// before the popup was ever opened, like the tray menu, and waits for a
+/// refresh that is already running, e.g. the one at launch.
+pub async fn ensure_token(app_handle: &tauri::AppHandle) {
+    let reauth_lock = app_handle.state::<ReauthLock>();
+    let _guard = reauth_lock.0.lock().await;
+
+    let app_store = app_handle.state::<AppStore>();
+    let has_token = app_store
+        .spotify_client
+        .token
+        .lock()
+        .await
+        .unwrap()
+        .as_ref()
+        .map_or(false, |token| {
+            !token.access_token.is_empty() && !token.is_expired()
+        });
+    if !has_token {
+        if let Err(error) = refresh(app_handle).await {
+            tracing::warn!(%error, "no spotify token");
+        }
+    }
+}
@@ -4,10 +4,27 @@ use std::{
     net::{TcpListener, TcpStream},
 };
 
-pub fn redirect_uri_web_server(
-    spotify_oauth: &mut AuthCodeSpotify,
-    port: u16,
-) -> Result<String, ()> {
+pub const DEFAULT_REDIRECT_URI_PORT: u16 = 8585;
+
+/// Points `redirect_uri` at `port`, keeping the rest of it as registered
+/// with Spotify, which compares it character by character.
+pub fn with_port(redirect_uri: &str, port: u16) -> String {
+    let mut url = match url::Url::parse(redirect_uri) {
+        Ok(url) if url.port_or_known_default() != Some(port) => url,
+        _ => return redirect_uri.to_string(),
+    };
+    if url.set_port(Some(port)).is_err() {
+        return redirect_uri.to_string();
+    }
+
+    let mut rewritten = url.to_string();
+    if !redirect_uri.ends_with('/') && url.path() == "/" {
+        rewritten.pop();
+    }
+    rewritten
+}
+
+pub fn redirect_uri_web_server(spotify_oauth: &AuthCodeSpotify, port: u16) -> Result<String, ()> {
     let listener = TcpListener::bind(format!("127.0.0.1:{}", port));
 
     let url = spotify_oauth.get_authorize_url(false).unwrap();
@@ -15,11 +32,11 @@ pub fn redirect_uri_web_server(
     match listener {
         Ok(listener) => {
             match webbrowser::open(&url) {
-                Ok(_) => println!("Opened {} in your browser.", url),
-                Err(why) => eprintln!(
-                    "Error when trying to open an URL in your browser: {:?}. \
-                     Please navigate here manually: {}",
-                    why, url
+                Ok(_) => tracing::info!(%url, "opened authorize url in browser"),
+                Err(why) => tracing::error!(
+                    ?why,
+                    %url,
+                    "failed to open authorize url in browser, navigate there manually"
                 ),
             }
 
@@ -31,13 +48,13 @@ pub fn redirect_uri_web_server(
                         }
                     }
                     Err(e) => {
-                        println!("Error: {}", e);
+                        tracing::warn!(error = %e, "redirect uri connection failed");
                     }
                 };
             }
         }
         Err(e) => {
-            println!("Error: {}", e);
+            tracing::error!(error = %e, port, "failed to bind redirect uri server");
         }
     }
 
@@ -83,7 +100,7 @@ fn respond_with_success(mut stream: TcpStream) {
 }
 
 fn respond_with_error(error_message: String, mut stream: TcpStream) {
-    println!("Error: {}", error_message);
+    tracing::warn!(%error_message, "bad redirect uri request");
     let response = format!(
         "HTTP/1.1 400 Bad Request\r\n\r\n400 - Bad Request - {}",
         error_message
@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::Mutex as SyncMutex;
+use std::time::Duration;
+use tauri::{LogicalSize, Manager};
+use tauri_plugin_store::{with_store, StoreCollection};
+
+use crate::http_api;
+use crate::paths;
+use crate::redirect_uri::DEFAULT_REDIRECT_URI_PORT;
+use crate::state::SimplifiedItem;
+use crate::store_file;
+use crate::window::{self, WindowLayout};
+
+pub const STORE_SETTINGS_KEY: &str = "settings";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Size {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl From<Size> for LogicalSize<f64> {
+    fn from(size: Size) -> Self {
+        LogicalSize::new(size.width, size.height)
+    }
+}
+
+/// The window size of each layout, in logical pixels.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct WindowSizes {
+    pub compact: Size,
+    pub standard: Size,
+    pub expanded: Size,
+}
+
+impl Default for WindowSizes {
+    fn default() -> Self {
+        Self {
+            compact: Size {
+                width: 320.,
+                height: 64.,
+            },
+            standard: Size {
+                width: 200.,
+                height: 340.,
+            },
+            expanded: Size {
+                width: 360.,
+                height: 560.,
+            },
+        }
+    }
+}
+
+impl WindowSizes {
+    pub fn get(&self, layout: WindowLayout) -> Size {
+        match layout {
+            WindowLayout::Compact => self.compact,
+            WindowLayout::Standard => self.standard,
+            WindowLayout::Expanded => self.expanded,
+        }
+    }
+}
+
+/// The scripting API and the now playing stream. Off unless turned on,
+/// since anything that has the token can then control playback.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HttpApiSettings {
+    pub enabled: bool,
+    pub port: u16,
+    /// Loopback by default. Anything else exposes the API to the network,
+    /// e.g. for an overlay rendered on another machine.
+    pub bind_address: IpAddr,
+    /// Web pages allowed to connect, by their `Origin`, e.g. `null` for a
+    /// local file in an OBS browser source. Clients that send no origin,
+    /// like scripts, are always allowed.
+    pub allowed_origins: Vec<String>,
+}
+
+impl Default for HttpApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 8586,
+            bind_address: IpAddr::V4(Ipv4Addr::LOCALHOST),
+            allowed_origins: Vec::new(),
+        }
+    }
+}
+
+/// Tunables that used to be constants. Fields missing from the store take
+/// their default, so new settings don't invalidate what was saved before.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// How often playback is polled while the player is open.
+    pub playback_poll_ms: u64,
+    /// How often playback is polled while only the tray is showing.
+    pub background_playback_poll_ms: u64,
+    /// Covers closest to this width are picked from the available sizes.
+    pub preferred_image_width_px: u32,
+    /// The port of the login callback. It has to match the port of the
+    /// redirect URI registered with Spotify, and applies after a restart.
+    pub redirect_uri_port: u16,
+    pub window_sizes: WindowSizes,
+    pub http_api: HttpApiSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            playback_poll_ms: 5_000,
+            background_playback_poll_ms: 15_000,
+            preferred_image_width_px: 200,
+            redirect_uri_port: DEFAULT_REDIRECT_URI_PORT,
+            window_sizes: WindowSizes::default(),
+            http_api: HttpApiSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Spotify rate limits aggressive polling, so anything below this is refused.
+    const MIN_POLL_MS: u64 = 1_000;
+    const MAX_POLL_MS: u64 = 10 * 60 * 1_000;
+    const IMAGE_WIDTH_PX: std::ops::RangeInclusive<u32> = 64..=2_000;
+    const WINDOW_SIDE: std::ops::RangeInclusive<f64> = 48.0..=4_000.0;
+
+    pub fn playback_call_buffer(&self) -> Duration {
+        Duration::from_millis(self.playback_poll_ms)
+    }
+
+    pub fn background_playback_call_buffer(&self) -> Duration {
+        Duration::from_millis(self.background_playback_poll_ms)
+    }
+
+    pub fn window_size(&self, layout: WindowLayout) -> LogicalSize<f64> {
+        self.window_sizes.get(layout).into()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let polls = [
+            ("playbackPollMs", self.playback_poll_ms),
+            ("backgroundPlaybackPollMs", self.background_playback_poll_ms),
+        ];
+        for (name, value) in polls {
+            if !(Self::MIN_POLL_MS..=Self::MAX_POLL_MS).contains(&value) {
+                return Err(format!(
+                    "{} must be between {} and {}",
+                    name,
+                    Self::MIN_POLL_MS,
+                    Self::MAX_POLL_MS
+                ));
+            }
+        }
+        if self.background_playback_poll_ms < self.playback_poll_ms {
+            return Err("backgroundPlaybackPollMs must not be below playbackPollMs".into());
+        }
+
+        if !Self::IMAGE_WIDTH_PX.contains(&self.preferred_image_width_px) {
+            return Err(format!(
+                "preferredImageWidthPx must be between {} and {}",
+                Self::IMAGE_WIDTH_PX.start(),
+                Self::IMAGE_WIDTH_PX.end()
+            ));
+        }
+
+        if self.redirect_uri_port < 1024 {
+            return Err("redirectUriPort must be 1024 or above".into());
+        }
+        if self.http_api.port < 1024 {
+            return Err("httpApi.port must be 1024 or above".into());
+        }
+        if self.http_api.port == self.redirect_uri_port {
+            return Err("httpApi.port must differ from redirectUriPort".into());
+        }
+
+        let sizes = [
+            ("compact", self.window_sizes.compact),
+            ("standard", self.window_sizes.standard),
+            ("expanded", self.window_sizes.expanded),
+        ];
+        for (name, size) in sizes {
+            if !Self::WINDOW_SIDE.contains(&size.width) || !Self::WINDOW_SIDE.contains(&size.height)
+            {
+                return Err(format!(
+                    "windowSizes.{} must be between {} and {} on each side",
+                    name,
+                    Self::WINDOW_SIDE.start(),
+                    Self::WINDOW_SIDE.end()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub struct SettingsState(pub SyncMutex<Settings>);
+
+/// Settings that fail validation, e.g. after a hand edit of the store, are
+/// dropped in favour of the defaults rather than half applied.
+pub fn load(app_handle: &tauri::AppHandle) -> Settings {
+    let collection = app_handle.state::<StoreCollection>();
+    let settings = with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| Ok(store.cache.get(STORE_SETTINGS_KEY).cloned()),
+    )
+    .ok()
+    .flatten()
+    .and_then(|value| serde_json::from_value::<Settings>(value).ok())
+    .unwrap_or_default();
+
+    match settings.validate() {
+        Ok(()) => settings,
+        Err(error) => {
+            tracing::warn!(%error, "invalid stored settings, using defaults");
+            Settings::default()
+        }
+    }
+}
+
+pub fn get(app_handle: &tauri::AppHandle) -> Settings {
+    app_handle
+        .state::<SettingsState>()
+        .0
+        .lock()
+        .unwrap()
+        .clone()
+}
+
+/// Pushes settings that are cached outside of the managed state.
+pub fn apply(app_handle: &tauri::AppHandle, settings: &Settings) {
+    SimplifiedItem::set_preferred_image_width(settings.preferred_image_width_px);
+    http_api::apply(app_handle, &settings.http_api);
+    if let Err(error) = window::apply_settings(app_handle, settings) {
+        tracing::warn!(%error, "failed to apply window settings");
+    }
+}
+
+/// Validates, stores and applies `settings`, then tells the frontend.
+pub fn update(app_handle: &tauri::AppHandle, settings: Settings) -> Result<(), String> {
+    settings.validate()?;
+
+    let serialized_settings = serde_json::to_value(&settings).map_err(|e| e.to_string())?;
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| {
+            Ok(store
+                .cache
+                .insert(STORE_SETTINGS_KEY.to_string(), serialized_settings))
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    store_file::schedule_save(app_handle);
+
+    *app_handle.state::<SettingsState>().0.lock().unwrap() = settings.clone();
+    apply(app_handle, &settings);
+    let _ = app_handle.emit_all("settings_changed", &settings);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(change: impl FnOnce(&mut Settings)) -> Result<(), String> {
+        let mut settings = Settings::default();
+        change(&mut settings);
+        settings.validate()
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(Settings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn poll_intervals_are_bounded() {
+        assert!(check(|s| s.playback_poll_ms = 999).is_err());
+        assert!(check(|s| s.background_playback_poll_ms = 11 * 60 * 1_000).is_err());
+        assert!(check(|s| {
+            s.playback_poll_ms = 1_000;
+            s.background_playback_poll_ms = 1_000;
+        })
+        .is_ok());
+    }
+
+    #[test]
+    fn background_polls_are_not_more_frequent() {
+        let error = check(|s| {
+            s.playback_poll_ms = 10_000;
+            s.background_playback_poll_ms = 5_000;
+        })
+        .unwrap_err();
+        assert!(error.contains("backgroundPlaybackPollMs"), "{}", error);
+    }
+
+    #[test]
+    fn image_width_is_bounded() {
+        assert!(check(|s| s.preferred_image_width_px = 63).is_err());
+        assert!(check(|s| s.preferred_image_width_px = 2_001).is_err());
+        assert!(check(|s| s.preferred_image_width_px = 640).is_ok());
+    }
+
+    #[test]
+    fn ports_are_unprivileged_and_distinct() {
+        assert!(check(|s| s.redirect_uri_port = 80).is_err());
+        assert!(check(|s| s.http_api.port = 443).is_err());
+        assert!(check(|s| s.http_api.port = s.redirect_uri_port).is_err());
+    }
+
+    #[test]
+    fn window_sizes_are_bounded() {
+        let error = check(|s| s.window_sizes.expanded.width = 10.0).unwrap_err();
+        assert!(error.contains("windowSizes.expanded"), "{}", error);
+        assert!(check(|s| s.window_sizes.compact.height = 5_000.0).is_err());
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let settings: Settings =
+            serde_json::from_value(serde_json::json!({ "playbackPollMs": 2_000 })).unwrap();
+        assert_eq!(settings.playback_poll_ms, 2_000);
+        assert_eq!(settings.http_api, HttpApiSettings::default());
+    }
+}
@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex as SyncMutex;
+use tauri::{GlobalShortcutManager, Manager};
+use tauri_plugin_store::{with_store, StoreCollection};
+
+use crate::handlers::control::{self, ControlCommand};
+use crate::paths;
+use crate::store_file;
+
+pub const STORE_SHORTCUTS_KEY: &str = "shortcuts";
+pub const SEEK_STEP_MS: i64 = 10_000;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum ShortcutAction {
+    PlayPause,
+    NextTrack,
+    PrevTrack,
+    ToggleSaved,
+    SeekForward,
+    SeekBackward,
+}
+
+impl ShortcutAction {
+    pub fn name(&self) -> &'static str {
+        use ShortcutAction::*;
+        match self {
+            PlayPause => "play_pause",
+            NextTrack => "next_track",
+            PrevTrack => "prev_track",
+            ToggleSaved => "toggle_saved",
+            SeekForward => "seek_forward",
+            SeekBackward => "seek_backward",
+        }
+    }
+}
+
+/// Maps each action to an accelerator such as `CmdOrCtrl+Alt+Space`.
+pub type ShortcutBindings = BTreeMap<ShortcutAction, String>;
+
+pub fn default_bindings() -> ShortcutBindings {
+    use ShortcutAction::*;
+    BTreeMap::from([
+        (PlayPause, "CmdOrCtrl+Alt+Space".into()),
+        (NextTrack, "CmdOrCtrl+Alt+Right".into()),
+        (PrevTrack, "CmdOrCtrl+Alt+Left".into()),
+        (ToggleSaved, "CmdOrCtrl+Alt+L".into()),
+        (SeekForward, "CmdOrCtrl+Alt+Shift+Right".into()),
+        (SeekBackward, "CmdOrCtrl+Alt+Shift+Left".into()),
+    ])
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortcutConflict {
+    pub action: ShortcutAction,
+    pub accelerator: String,
+    pub reason: String,
+}
+
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ShortcutState {
+    pub bindings: ShortcutBindings,
+    pub conflicts: Vec<ShortcutConflict>,
+}
+
+#[derive(Default)]
+pub struct Shortcuts(pub SyncMutex<ShortcutState>);
+
+async fn dispatch(app_handle: &tauri::AppHandle, action: ShortcutAction) {
+    use ShortcutAction::*;
+    let command = match action {
+        PlayPause => ControlCommand::PlayPause,
+        NextTrack => ControlCommand::NextTrack,
+        PrevTrack => ControlCommand::PrevTrack,
+        ToggleSaved => ControlCommand::ToggleSaved,
+        SeekForward => ControlCommand::SeekBy(SEEK_STEP_MS),
+        SeekBackward => ControlCommand::SeekBy(-SEEK_STEP_MS),
+    };
+    let _ = control::execute(app_handle, command).await;
+}
+
+/// Replaces every registered shortcut with `bindings`. Bindings that can't be
+/// registered are skipped and returned as conflicts, and also emitted as
+/// `shortcut_conflicts` so the UI can show them.
+pub fn register(
+    app_handle: &tauri::AppHandle,
+    bindings: ShortcutBindings,
+) -> Vec<ShortcutConflict> {
+    let mut manager = app_handle.global_shortcut_manager();
+    if let Err(error) = manager.unregister_all() {
+        tracing::warn!(%error, "failed to unregister global shortcuts");
+    }
+
+    let mut conflicts = Vec::new();
+    let mut taken: BTreeMap<String, ShortcutAction> = BTreeMap::new();
+
+    for (&action, accelerator) in &bindings {
+        if accelerator.is_empty() {
+            continue;
+        }
+
+        if let Some(other) = taken.get(&accelerator.to_lowercase()) {
+            conflicts.push(ShortcutConflict {
+                action,
+                accelerator: accelerator.clone(),
+                reason: format!("already bound to {}", other.name()),
+            });
+            continue;
+        }
+
+        let handler_app_handle = app_handle.clone();
+        let result = manager.register(accelerator, move || {
+            let app_handle = handler_app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                dispatch(&app_handle, action).await;
+            });
+        });
+
+        match result {
+            Ok(()) => {
+                taken.insert(accelerator.to_lowercase(), action);
+            }
+            Err(error) => conflicts.push(ShortcutConflict {
+                action,
+                accelerator: accelerator.clone(),
+                reason: error.to_string(),
+            }),
+        }
+    }
+
+    for conflict in &conflicts {
+        tracing::warn!(action = conflict.action.name(), accelerator = %conflict.accelerator, reason = %conflict.reason, "global shortcut not registered");
+    }
+    let _ = app_handle.emit_all("shortcut_conflicts", conflicts.clone());
+
+    *app_handle.state::<Shortcuts>().0.lock().unwrap() = ShortcutState {
+        bindings,
+        conflicts: conflicts.clone(),
+    };
+    conflicts
+}
+
+pub fn load_bindings(app_handle: &tauri::AppHandle) -> ShortcutBindings {
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| Ok(store.cache.get(STORE_SHORTCUTS_KEY).cloned()),
+    )
+    .ok()
+    .flatten()
+    .and_then(|value| serde_json::from_value(value).ok())
+    .unwrap_or_else(default_bindings)
+}
+
+pub fn save_bindings(
+    app_handle: &tauri::AppHandle,
+    bindings: &ShortcutBindings,
+) -> Result<(), String> {
+    let serialized_bindings = serde_json::to_value(bindings).map_err(|e| e.to_string())?;
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| {
+            Ok(store
+                .cache
+                .insert(STORE_SHORTCUTS_KEY.to_string(), serialized_bindings))
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    store_file::schedule_save(app_handle);
+    Ok(())
+}
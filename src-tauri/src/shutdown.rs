@@ -0,0 +1,58 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tauri::Manager;
+
+use crate::handlers::unsubscribe_to_event_loop;
+use crate::last_playback;
+use crate::spotify_request;
+use crate::state::AppStore;
+use crate::store_file;
+use crate::telemetry::LogGuard;
+
+/// How long quitting waits for in-flight requests before giving up on them.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_millis(50);
+
+static QUITTING: AtomicBool = AtomicBool::new(false);
+
+/// Whether the user asked to quit. Every other exit request only closes
+/// windows, and the app keeps running in the tray.
+pub fn is_quitting() -> bool {
+    QUITTING.load(Ordering::SeqCst)
+}
+
+pub fn save_store(app_handle: &tauri::AppHandle) {
+    if let Err(error) = store_file::save_now(app_handle) {
+        tracing::error!(%error, "failed to save store");
+    }
+}
+
+async fn wait_for_requests() {
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while spotify_request::in_flight() > 0 && Instant::now() < deadline {
+        tokio::time::sleep(IDLE_CHECK_INTERVAL).await;
+    }
+
+    let in_flight = spotify_request::in_flight();
+    if in_flight > 0 {
+        tracing::warn!(in_flight, "quitting with spotify requests still in flight");
+    }
+}
+
+/// Stops polling, lets commands that are still talking to Spotify finish
+/// their retries, writes the store and exits. Calling it again while a quit
+/// is under way does nothing.
+pub async fn quit(app_handle: tauri::AppHandle) {
+    if QUITTING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    tracing::info!("quitting");
+
+    unsubscribe_to_event_loop(&app_handle).unwrap();
+    wait_for_requests().await;
+    last_playback::save_state(&app_handle, &app_handle.state::<AppStore>().snapshot());
+    save_store(&app_handle);
+
+    drop(app_handle.state::<LogGuard>().0.lock().unwrap().take());
+    app_handle.exit(0);
+}
@@ -0,0 +1,165 @@
+use rspotify::http::HttpError;
+use rspotify::{ClientError, ClientResult};
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use tracing::{field, Instrument};
+
+use crate::error_log::{http_status, ErrorKind, Severity};
+
+pub const MAX_RETRIES: u32 = 2;
+pub const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+/// Longer waits asked for by Spotify fail the request instead, since callers
+/// are mostly waiting on a button press.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+static RATE_LIMITED_UNTIL_MS: AtomicI64 = AtomicI64::new(0);
+static RATE_LIMIT_HITS: AtomicU64 = AtomicU64::new(0);
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// Unix time in ms until which Spotify asked us to back off, if that's still in the future.
+pub fn rate_limited_until_ms() -> Option<i64> {
+    let until = RATE_LIMITED_UNTIL_MS.load(Ordering::Relaxed);
+    (until > chrono::Utc::now().timestamp_millis()).then_some(until)
+}
+
+pub fn rate_limit_hits() -> u64 {
+    RATE_LIMIT_HITS.load(Ordering::Relaxed)
+}
+
+/// Requests that were sent but haven't finished yet, retries included.
+pub fn in_flight() -> usize {
+    IN_FLIGHT.load(Ordering::Relaxed)
+}
+
+/// Counts a request as in flight for as long as it is alive, so cancelled
+/// requests are released too.
+struct InFlightGuard;
+
+impl InFlightGuard {
+    fn new() -> Self {
+        IN_FLIGHT.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn record_rate_limit(backoff: Duration) {
+    let until = chrono::Utc::now().timestamp_millis() + backoff.as_millis() as i64;
+    RATE_LIMITED_UNTIL_MS.fetch_max(until, Ordering::Relaxed);
+    RATE_LIMIT_HITS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn retry_after(error: &ClientError) -> Option<Duration> {
+    match error {
+        ClientError::Http(error) => match error.as_ref() {
+            HttpError::StatusCode(response) => response
+                .headers()
+                .get("retry-after")
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs),
+            HttpError::Client(_) => None,
+        },
+        _ => None,
+    }
+}
+
+/// Sends a request to Spotify inside a `spotify_request` span. Only rate
+/// limited requests are retried, since Spotify may have applied one that
+/// failed otherwise, and skipping or saving twice is worse than failing.
+pub async fn send<T, F, Fut>(request: &'static str, call: F) -> ClientResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ClientResult<T>>,
+{
+    send_with_retries(request, false, call).await
+}
+
+/// Like [`send`], but also retries 5xx and network errors. Only for requests
+/// that are safe to repeat, such as reading the current playback.
+pub async fn fetch<T, F, Fut>(request: &'static str, call: F) -> ClientResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ClientResult<T>>,
+{
+    send_with_retries(request, true, call).await
+}
+
+fn should_retry(error: &ClientError, retries: u32, idempotent: bool) -> bool {
+    if retries >= MAX_RETRIES {
+        return false;
+    }
+    match ErrorKind::from(error) {
+        ErrorKind::RateLimited => retry_after(error).map_or(true, |wait| wait <= MAX_RETRY_AFTER),
+        kind => idempotent && kind.severity() == Severity::Warning,
+    }
+}
+
+async fn send_with_retries<T, F, Fut>(
+    request: &'static str,
+    idempotent: bool,
+    mut call: F,
+) -> ClientResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = ClientResult<T>>,
+{
+    let span = tracing::info_span!(
+        "spotify_request",
+        request,
+        status = field::Empty,
+        retries = field::Empty,
+        latency_ms = field::Empty,
+    );
+
+    async move {
+        let _in_flight = InFlightGuard::new();
+        let start = Instant::now();
+        let mut retries = 0;
+
+        let result = loop {
+            let result = call().await;
+            let error = match &result {
+                Ok(_) => break result,
+                Err(error) => error,
+            };
+            if ErrorKind::from(error) == ErrorKind::RateLimited {
+                record_rate_limit(retry_after(error).unwrap_or(RETRY_BACKOFF));
+            }
+            if !should_retry(error, retries, idempotent) {
+                break result;
+            }
+
+            retries += 1;
+            let backoff = retry_after(error).unwrap_or(RETRY_BACKOFF * retries);
+            tracing::warn!(%error, retries, ?backoff, "retrying spotify request");
+            tokio::time::sleep(backoff).await;
+        };
+
+        let span = tracing::Span::current();
+        span.record("retries", retries);
+        span.record("latency_ms", start.elapsed().as_millis() as u64);
+        match &result {
+            Ok(_) => {
+                span.record("status", "ok");
+            }
+            Err(error) => {
+                match http_status(error) {
+                    Some(status) => span.record("status", status),
+                    None => span.record("status", "error"),
+                };
+                tracing::warn!(%error, "spotify request failed");
+            }
+        }
+
+        result
+    }
+    .instrument(span)
+    .await
+}
@@ -0,0 +1,53 @@
+use tokio::sync::{mpsc, oneshot, watch};
+
+use super::AppState;
+use crate::{last_playback, notifications, tray};
+
+pub type Mutation = Box<dyn FnOnce(&mut AppState) + Send>;
+
+pub enum StateCommand {
+    /// Applies the mutation and replies with the state as it was before it.
+    Update(Mutation, oneshot::Sender<AppState>),
+}
+
+/// Owns the only mutable copy of [`AppState`]. Every change goes through the
+/// command channel, so no caller ever holds the state across an `.await`.
+pub struct StateActor {
+    state: AppState,
+    commands: mpsc::UnboundedReceiver<StateCommand>,
+    snapshot: watch::Sender<AppState>,
+    app_handle: tauri::AppHandle,
+}
+
+impl StateActor {
+    pub fn new(
+        state: AppState,
+        commands: mpsc::UnboundedReceiver<StateCommand>,
+        snapshot: watch::Sender<AppState>,
+        app_handle: tauri::AppHandle,
+    ) -> Self {
+        Self {
+            state,
+            commands,
+            snapshot,
+            app_handle,
+        }
+    }
+
+    pub async fn run(mut self) {
+        while let Some(command) = self.commands.recv().await {
+            match command {
+                StateCommand::Update(mutation, reply) => {
+                    let prev = self.state.clone();
+                    mutation(&mut self.state);
+                    let events = self.state.emit_changes(&prev, &self.app_handle);
+                    tray::on_state_change(&self.app_handle, &self.state, &events);
+                    notifications::on_state_change(&self.app_handle, &self.state, &events);
+                    last_playback::on_state_change(&self.app_handle, &self.state, &events);
+                    self.snapshot.send_replace(self.state.clone());
+                    let _ = reply.send(prev);
+                }
+            }
+        }
+    }
+}
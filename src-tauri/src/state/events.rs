@@ -0,0 +1,227 @@
+use rspotify::model::RepeatState;
+use serde::Serialize;
+use tauri::Manager;
+
+use super::{AppState, SimplifiedItem};
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", content = "payload", rename_all = "snake_case")]
+pub enum PlaybackEvent {
+    TrackChanged(Option<SimplifiedItem>),
+    PlaybackPaused(u64),
+    PlaybackResumed(u64),
+    ShuffleChanged(bool),
+    RepeatChanged(RepeatState),
+    DeviceChanged(Option<String>),
+    SavedChanged(bool),
+    ProgressChanged(u64),
+    StaleChanged(bool),
+}
+
+impl PlaybackEvent {
+    pub fn name(&self) -> &'static str {
+        use PlaybackEvent::*;
+        match self {
+            TrackChanged(_) => "track_changed",
+            PlaybackPaused(_) => "playback_paused",
+            PlaybackResumed(_) => "playback_resumed",
+            ShuffleChanged(_) => "shuffle_changed",
+            RepeatChanged(_) => "repeat_changed",
+            DeviceChanged(_) => "device_changed",
+            SavedChanged(_) => "saved_changed",
+            ProgressChanged(_) => "progress_changed",
+            StaleChanged(_) => "stale_changed",
+        }
+    }
+
+    pub fn emit(&self, app_handle: &tauri::AppHandle) -> tauri::Result<()> {
+        use PlaybackEvent::*;
+        match self {
+            TrackChanged(item) => app_handle.emit_all(self.name(), item),
+            PlaybackPaused(progress_ms)
+            | PlaybackResumed(progress_ms)
+            | ProgressChanged(progress_ms) => app_handle.emit_all(self.name(), progress_ms),
+            ShuffleChanged(shuffle) => app_handle.emit_all(self.name(), shuffle),
+            RepeatChanged(repeat_state) => app_handle.emit_all(self.name(), repeat_state),
+            DeviceChanged(device_id) => app_handle.emit_all(self.name(), device_id),
+            SavedChanged(saved) => app_handle.emit_all(self.name(), saved),
+            StaleChanged(stale) => app_handle.emit_all(self.name(), stale),
+        }
+    }
+
+    /// Computes the events needed to bring a listener from `prev` to `next`.
+    pub fn diff(prev: &AppState, next: &AppState) -> Vec<PlaybackEvent> {
+        use PlaybackEvent::*;
+        let mut events = Vec::new();
+
+        let prev_id = prev.curr.as_ref().and_then(|c| c.id.as_ref());
+        let next_id = next.curr.as_ref().and_then(|c| c.id.as_ref());
+        let track_changed = prev.curr.is_some() != next.curr.is_some() || prev_id != next_id;
+
+        if track_changed {
+            events.push(TrackChanged(next.curr.clone()));
+        } else if let (Some(prev_curr), Some(next_curr)) = (&prev.curr, &next.curr) {
+            if prev_curr.saved != next_curr.saved {
+                events.push(SavedChanged(next_curr.saved));
+            }
+        }
+
+        if prev.playing != next.playing {
+            events.push(match next.playing {
+                true => PlaybackResumed(next.progress_ms),
+                false => PlaybackPaused(next.progress_ms),
+            });
+        } else if prev.progress_ms != next.progress_ms {
+            events.push(ProgressChanged(next.progress_ms));
+        }
+
+        if prev.shuffle != next.shuffle {
+            events.push(ShuffleChanged(next.shuffle));
+        }
+
+        if prev.repeat_state != next.repeat_state {
+            events.push(RepeatChanged(next.repeat_state));
+        }
+
+        if prev.device_id != next.device_id {
+            events.push(DeviceChanged(next.device_id.clone()));
+        }
+
+        if prev.stale != next.stale {
+            events.push(StaleChanged(next.stale));
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::PlayableId;
+    use rspotify::model::TrackId;
+
+    fn item(id: &str, saved: bool) -> SimplifiedItem {
+        SimplifiedItem {
+            id: Some(PlayableId::Track(
+                TrackId::from_id(id).unwrap().into_static(),
+            )),
+            name: Some(id.to_string()),
+            saved,
+            duration_ms: 200_000,
+            ..SimplifiedItem::default()
+        }
+    }
+
+    fn state(curr: Option<SimplifiedItem>) -> AppState {
+        AppState {
+            curr,
+            ..AppState::default()
+        }
+    }
+
+    fn names(events: &[PlaybackEvent]) -> Vec<&'static str> {
+        events.iter().map(PlaybackEvent::name).collect()
+    }
+
+    #[test]
+    fn same_state_has_no_events() {
+        let state = state(Some(item("4iV5W9uYEdYUVa79Axb7Rh", false)));
+        assert!(PlaybackEvent::diff(&state, &state).is_empty());
+    }
+
+    #[test]
+    fn track_change() {
+        let prev = state(Some(item("4iV5W9uYEdYUVa79Axb7Rh", false)));
+        let next = state(Some(item("1301WleyT98MSxVHPZCA6M", true)));
+        let events = PlaybackEvent::diff(&prev, &next);
+        // The new item carries its own saved flag, so no `saved_changed`.
+        assert_eq!(names(&events), ["track_changed"]);
+
+        let events = PlaybackEvent::diff(&next, &state(None));
+        assert!(matches!(
+            events.as_slice(),
+            [PlaybackEvent::TrackChanged(None)]
+        ));
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let paused = AppState {
+            progress_ms: 1_000,
+            ..state(Some(item("4iV5W9uYEdYUVa79Axb7Rh", false)))
+        };
+        let playing = AppState {
+            playing: true,
+            progress_ms: 2_000,
+            ..paused.clone()
+        };
+
+        let events = PlaybackEvent::diff(&paused, &playing);
+        assert!(matches!(
+            events.as_slice(),
+            [PlaybackEvent::PlaybackResumed(2_000)]
+        ));
+        let events = PlaybackEvent::diff(&playing, &paused);
+        assert!(matches!(
+            events.as_slice(),
+            [PlaybackEvent::PlaybackPaused(1_000)]
+        ));
+
+        let later = AppState {
+            progress_ms: 3_000,
+            ..playing.clone()
+        };
+        let events = PlaybackEvent::diff(&playing, &later);
+        assert!(matches!(
+            events.as_slice(),
+            [PlaybackEvent::ProgressChanged(3_000)]
+        ));
+    }
+
+    #[test]
+    fn shuffle_and_repeat() {
+        let prev = state(None);
+        let next = AppState {
+            shuffle: true,
+            repeat_state: RepeatState::Track,
+            ..state(None)
+        };
+        let events = PlaybackEvent::diff(&prev, &next);
+        assert!(matches!(
+            events.as_slice(),
+            [
+                PlaybackEvent::ShuffleChanged(true),
+                PlaybackEvent::RepeatChanged(RepeatState::Track)
+            ]
+        ));
+    }
+
+    #[test]
+    fn saved() {
+        let prev = state(Some(item("4iV5W9uYEdYUVa79Axb7Rh", false)));
+        let next = state(Some(item("4iV5W9uYEdYUVa79Axb7Rh", true)));
+        let events = PlaybackEvent::diff(&prev, &next);
+        assert!(matches!(
+            events.as_slice(),
+            [PlaybackEvent::SavedChanged(true)]
+        ));
+    }
+
+    #[test]
+    fn stale() {
+        let restored = AppState {
+            stale: true,
+            ..state(Some(item("4iV5W9uYEdYUVa79Axb7Rh", false)))
+        };
+        let polled = AppState {
+            stale: false,
+            ..restored.clone()
+        };
+        let events = PlaybackEvent::diff(&restored, &polled);
+        assert!(matches!(
+            events.as_slice(),
+            [PlaybackEvent::StaleChanged(false)]
+        ));
+    }
+}
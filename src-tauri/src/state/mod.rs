@@ -1,25 +1,31 @@
 use crate::helpers::to_string;
+use crate::last_playback;
+use crate::spotify_request;
+use crate::tray;
 
+use self::actor::{StateActor, StateCommand};
+pub use self::events::PlaybackEvent;
+pub use self::saved_cache::SavedCache;
 pub use self::simplified_item::{PlayableId, SimplifiedItem};
 use rspotify::model::{AdditionalType, RepeatState, TrackId};
 use rspotify::prelude::OAuthClient;
 use rspotify::{AuthCodeSpotify, ClientError};
 use serde::Serialize;
+use std::path::PathBuf;
 use std::sync::Mutex as SyncMutex;
-use std::{
-    sync::Arc,
-    time::{Duration, Instant},
-};
-use tauri::async_runtime::{JoinHandle, Mutex};
-use tauri::Manager;
+use std::time::{Duration, Instant};
+use tauri::async_runtime::JoinHandle;
 use thiserror::Error;
+use tokio::sync::{mpsc, oneshot, watch};
 
+mod actor;
+mod events;
+mod saved_cache;
 mod simplified_item;
 
 pub struct EventLoopHandle(pub SyncMutex<Option<JoinHandle<()>>>);
 
-pub const STORE_PATH_BUF: &str = "store.bin";
-pub const STORE_TOKEN_KEY: &str = "access_token";
+pub const STORE_TOKEN_KEY: &str = "token";
 
 #[derive(Serialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -29,15 +35,26 @@ pub struct AppState {
     pub progress_ms: u64,
     pub playing: bool,
     pub repeat_state: RepeatState,
+    /// Set while the state is the one restored from the last run, until the
+    /// first poll replaces it.
+    pub stale: bool,
+    /// The cached cover of the restored item, shown while it is stale.
+    pub cover_path: Option<PathBuf>,
 
     #[serde(skip_serializing)]
     pub last_playback_call: Instant,
     #[serde(skip_serializing)]
-    pub spotify_client: Arc<Mutex<AuthCodeSpotify>>,
+    pub last_successful_poll_ms: Option<i64>,
     #[serde(skip_serializing)]
     pub last_seek_update: Instant,
     #[serde(skip_serializing)]
     pub device_id: Option<String>,
+    #[serde(skip_serializing)]
+    pub volume_percent: Option<u32>,
+    #[serde(skip_serializing)]
+    pub saved_cache: SavedCache,
+    #[serde(skip_serializing)]
+    pub local_edits: u64,
 }
 
 #[derive(Error, Serialize, Debug)]
@@ -55,83 +72,226 @@ impl From<ClientError> for GetCurrentPlaybackError {
 }
 
 impl AppState {
-    pub const PLAYBACK_CALL_BUFFER: Duration = Duration::from_secs(5);
     pub const SEEK_CALL_BUFFER: Duration = Duration::from_millis(1);
+    pub const BACKGROUND_SEEK_CALL_BUFFER: Duration = Duration::from_secs(1);
+    pub const ERROR_BACKOFF: Duration = Duration::from_secs(1);
 
-    pub fn emit_update(&self, app_handle: &tauri::AppHandle) {
-        app_handle
-            .emit_all("app_state_change", self.clone())
-            .unwrap();
-    }
-
-    pub async fn current_user_saved_tracks_contains(&self, id: &TrackId<'static>) -> bool {
-        let spotify_client = self.spotify_client.lock().await;
-        let saved_tracks = (*spotify_client)
-            .current_user_saved_tracks_contains(vec![id.clone()])
-            .await;
-
-        if let Ok(tracks) = saved_tracks {
-            match tracks[..] {
-                [liked] => liked,
-                _ => false,
+    pub fn emit_changes(
+        &self,
+        prev: &AppState,
+        app_handle: &tauri::AppHandle,
+    ) -> Vec<PlaybackEvent> {
+        let events = PlaybackEvent::diff(prev, self);
+        // Runs on the state actor, which must outlive a webview that is
+        // being torn down, e.g. during shutdown.
+        for event in &events {
+            if let Err(error) = event.emit(app_handle) {
+                tracing::warn!(%error, event = event.name(), "failed to emit state change");
             }
-        } else {
-            false
         }
+        events
     }
 
-    pub async fn get_current_playback(&mut self) -> Result<(), GetCurrentPlaybackError> {
-        use AdditionalType::*;
-
-        let context = {
-            let spotify_client = self.spotify_client.clone();
-            let spotify_client = spotify_client.lock().await;
-            {
-                let token = spotify_client.token.lock().await.unwrap();
-                token.as_ref().ok_or(GetCurrentPlaybackError::TokenNotSet)?;
-            }
-            (*spotify_client)
-                .current_playback(None, Some(vec![Track, Episode].iter()))
-                .await?
-        };
-
-        if let Some(context) = context {
-            self.playing = context.is_playing;
-            self.device_id = context.device.id.clone();
-            self.shuffle = context.shuffle_state;
-            self.repeat_state = context.repeat_state;
-
-            let mut item = SimplifiedItem::from(context);
-
-            item.saved = match &item.id {
-                Some(PlayableId::Track(id)) => self.current_user_saved_tracks_contains(id).await,
-                _ => false,
-            };
-
-            self.progress_ms = item.progress_ms;
-            self.curr = Some(item);
+    pub fn advance_progress(&mut self) {
+        if !self.playing || self.last_seek_update.elapsed() < Self::SEEK_CALL_BUFFER {
+            return;
         }
 
-        self.last_playback_call = Instant::now();
-
-        Ok(())
+        let elapsed = self.last_seek_update.elapsed().as_millis();
+        let diff = (elapsed / Self::SEEK_CALL_BUFFER.as_millis()) as u64;
+        if let Some(curr) = self.curr.as_mut() {
+            curr.progress_ms += diff;
+            self.progress_ms = curr.progress_ms;
+            self.last_seek_update = Instant::now();
+        }
     }
 }
 
 impl Default for AppState {
     fn default() -> Self {
         AppState {
-            spotify_client: Arc::default(),
             curr: None,
             shuffle: false,
             progress_ms: 0,
             playing: false,
             repeat_state: RepeatState::Off,
+            stale: false,
+            cover_path: None,
             last_playback_call: Instant::now(),
+            last_successful_poll_ms: None,
             last_seek_update: Instant::now(),
             device_id: None,
+            volume_percent: None,
+            saved_cache: SavedCache::default(),
+            local_edits: 0,
         }
     }
 }
 
-pub struct AppStore(pub Arc<Mutex<AppState>>);
+pub struct AppStore {
+    pub spotify_client: AuthCodeSpotify,
+    commands: mpsc::UnboundedSender<StateCommand>,
+    snapshot: watch::Receiver<AppState>,
+}
+
+impl AppStore {
+    pub fn spawn(app_handle: &tauri::AppHandle, spotify_client: AuthCodeSpotify) -> Self {
+        let mut state = AppState::default();
+        if let Some(last_playback) = last_playback::load(app_handle) {
+            last_playback.restore(&mut state);
+            tray::show_restored(app_handle, &state);
+        }
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+        let (snapshot_tx, snapshot_rx) = watch::channel(state.clone());
+
+        let actor = StateActor::new(state, commands_rx, snapshot_tx, app_handle.clone());
+        tauri::async_runtime::spawn(actor.run());
+
+        Self {
+            spotify_client,
+            commands: commands_tx,
+            snapshot: snapshot_rx,
+        }
+    }
+
+    pub fn snapshot(&self) -> AppState {
+        self.snapshot.borrow().clone()
+    }
+
+    /// Returns a receiver that is notified after every state change.
+    pub fn subscribe(&self) -> watch::Receiver<AppState> {
+        self.snapshot.clone()
+    }
+
+    /// Applies `mutation` on the state actor and returns the state from before it.
+    /// Should the actor have stopped, the change is lost and the last snapshot
+    /// returned instead.
+    pub async fn update<F>(&self, mutation: F) -> AppState
+    where
+        F: FnOnce(&mut AppState) + Send + 'static,
+    {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let command = StateCommand::Update(Box::new(mutation), reply_tx);
+        if self.commands.send(command).is_err() {
+            tracing::error!("state actor stopped, dropping state update");
+            return self.snapshot();
+        }
+        match reply_rx.await {
+            Ok(prev) => prev,
+            Err(_) => {
+                tracing::error!("state actor stopped before applying state update");
+                self.snapshot()
+            }
+        }
+    }
+
+    /// Same as [`AppStore::update`], but marks the change as a local edit so
+    /// that polls which started before it don't overwrite it.
+    pub async fn edit<F>(&self, mutation: F) -> AppState
+    where
+        F: FnOnce(&mut AppState) + Send + 'static,
+    {
+        self.update(move |state| {
+            mutation(state);
+            state.local_edits += 1;
+        })
+        .await
+    }
+
+    pub async fn current_user_saved_tracks_contains(&self, id: &TrackId<'static>) -> bool {
+        if let Some(saved) = self.snapshot.borrow().saved_cache.get(id) {
+            return saved;
+        }
+
+        let spotify_client = &self.spotify_client;
+        let saved_tracks =
+            spotify_request::fetch("current_user_saved_tracks_contains", move || {
+                spotify_client.current_user_saved_tracks_contains(vec![id.clone()])
+            })
+            .await;
+
+        if let Ok(tracks) = saved_tracks {
+            let saved = match tracks[..] {
+                [liked] => liked,
+                _ => false,
+            };
+            let id = id.clone();
+            self.update(move |state| state.saved_cache.insert(id, saved))
+                .await;
+            saved
+        } else {
+            false
+        }
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    pub async fn get_current_playback(&self) -> Result<(), GetCurrentPlaybackError> {
+        use AdditionalType::*;
+
+        let local_edits = self.snapshot.borrow().local_edits;
+
+        {
+            let token = self.spotify_client.token.lock().await.unwrap();
+            token.as_ref().ok_or(GetCurrentPlaybackError::TokenNotSet)?;
+        }
+        let spotify_client = &self.spotify_client;
+        let additional_types = &[Track, Episode];
+        let context = spotify_request::fetch("current_playback", move || {
+            spotify_client.current_playback(None, Some(additional_types.iter()))
+        })
+        .await?;
+
+        let playback = match context {
+            Some(context) => {
+                let playing = context.is_playing;
+                let device_id = context.device.id.clone();
+                let volume_percent = context.device.volume_percent;
+                let shuffle = context.shuffle_state;
+                let repeat_state = context.repeat_state;
+
+                let mut item = SimplifiedItem::from(context);
+                item.saved = match &item.id {
+                    Some(PlayableId::Track(id)) => {
+                        self.current_user_saved_tracks_contains(id).await
+                    }
+                    _ => false,
+                };
+
+                Some((
+                    playing,
+                    device_id,
+                    volume_percent,
+                    shuffle,
+                    repeat_state,
+                    item,
+                ))
+            }
+            None => None,
+        };
+
+        self.update(move |state| {
+            if let Some((playing, device_id, volume_percent, shuffle, repeat_state, item)) =
+                playback
+            {
+                // A command changed the state while this request was in flight,
+                // so this response may already be outdated.
+                if state.local_edits == local_edits {
+                    state.playing = playing;
+                    state.device_id = device_id;
+                    state.volume_percent = volume_percent;
+                    state.shuffle = shuffle;
+                    state.repeat_state = repeat_state;
+                    state.progress_ms = item.progress_ms;
+                    state.curr = Some(item);
+                }
+            }
+            state.stale = false;
+            state.cover_path = None;
+            state.last_playback_call = Instant::now();
+            state.last_successful_poll_ms = Some(chrono::Utc::now().timestamp_millis());
+        })
+        .await;
+
+        Ok(())
+    }
+}
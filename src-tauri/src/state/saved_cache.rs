@@ -0,0 +1,65 @@
+use rspotify::model::TrackId;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, Default)]
+pub struct SavedCache {
+    entries: HashMap<TrackId<'static>, (bool, Instant)>,
+}
+
+impl SavedCache {
+    pub const TTL: Duration = Duration::from_secs(60);
+
+    pub fn get(&self, id: &TrackId<'static>) -> Option<bool> {
+        self.entries
+            .get(id)
+            .filter(|(_, fetched_at)| fetched_at.elapsed() < Self::TTL)
+            .map(|(saved, _)| *saved)
+    }
+
+    pub fn insert(&mut self, id: TrackId<'static>, saved: bool) {
+        self.entries
+            .retain(|_, (_, fetched_at)| fetched_at.elapsed() < Self::TTL);
+        self.entries.insert(id, (saved, Instant::now()));
+    }
+
+    pub fn invalidate(&mut self, id: &TrackId<'static>) {
+        self.entries.remove(id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(id: &str) -> TrackId<'static> {
+        TrackId::from_id(id).unwrap().into_static()
+    }
+
+    #[test]
+    fn insert_get_and_invalidate() {
+        let mut cache = SavedCache::default();
+        let track = id("4iV5W9uYEdYUVa79Axb7Rh");
+        assert_eq!(cache.get(&track), None);
+
+        cache.insert(track.clone(), true);
+        assert_eq!(cache.get(&track), Some(true));
+        cache.insert(track.clone(), false);
+        assert_eq!(cache.get(&track), Some(false));
+
+        cache.invalidate(&track);
+        assert_eq!(cache.get(&track), None);
+    }
+
+    #[test]
+    fn expired_entries_are_ignored_and_pruned() {
+        let mut cache = SavedCache::default();
+        let old = id("4iV5W9uYEdYUVa79Axb7Rh");
+        let fetched_at = Instant::now() - SavedCache::TTL - Duration::from_secs(1);
+        cache.entries.insert(old.clone(), (true, fetched_at));
+        assert_eq!(cache.get(&old), None);
+
+        cache.insert(id("1301WleyT98MSxVHPZCA6M"), true);
+        assert!(!cache.entries.contains_key(&old));
+    }
+}
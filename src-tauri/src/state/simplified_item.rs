@@ -2,15 +2,16 @@ use rspotify::model::{
     CurrentPlaybackContext, EpisodeId, FullEpisode, FullTrack, Image, PlayableItem,
     SimplifiedAlbum, SimplifiedArtist, TrackId,
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU32, Ordering};
 
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum PlayableId {
     Track(TrackId<'static>),
     Episode(EpisodeId<'static>),
 }
 
-#[derive(Serialize, Clone, Debug, Default)]
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
 pub struct SimplifiedItem {
     pub context_uri: Option<String>,
@@ -24,25 +25,32 @@ pub struct SimplifiedItem {
     pub progress_ms: u64,
 }
 
+/// Read from the settings, but kept here since items are converted without
+/// access to the app.
+static PREFERRED_IMAGE_WIDTH_PX: AtomicU32 = AtomicU32::new(200);
+
 impl SimplifiedItem {
-    const PREFERRED_IMAGE_WIDTH_PX: u32 = 200;
-    fn get_id(playback: &CurrentPlaybackContext) -> Option<PlayableId> {
-        match &playback.item {
+    pub fn set_preferred_image_width(width_px: u32) {
+        PREFERRED_IMAGE_WIDTH_PX.store(width_px, Ordering::Relaxed);
+    }
+
+    fn get_id(item: Option<&PlayableItem>) -> Option<PlayableId> {
+        match item {
             Some(PlayableItem::Track(t)) => t.id.as_ref().map(|t| PlayableId::Track(t.clone())),
             Some(PlayableItem::Episode(e)) => Some(PlayableId::Episode(e.id.clone())),
             _ => None,
         }
     }
 
-    fn get_artists(playback: &CurrentPlaybackContext) -> Vec<SimplifiedArtist> {
-        match &playback.item {
+    fn get_artists(item: Option<&PlayableItem>) -> Vec<SimplifiedArtist> {
+        match item {
             Some(PlayableItem::Track(t)) => t.artists.clone(),
             _ => Vec::new(),
         }
     }
 
-    fn get_album(playback: &CurrentPlaybackContext) -> Option<SimplifiedAlbum> {
-        match &playback.item {
+    fn get_album(item: Option<&PlayableItem>) -> Option<SimplifiedAlbum> {
+        match item {
             Some(PlayableItem::Track(t)) => Some(t.album.clone()),
             _ => None,
         }
@@ -50,7 +58,7 @@ impl SimplifiedItem {
 
     fn get_preferred_image(images: &[Image]) -> Option<&Image> {
         images.iter().reduce(|accum, item| {
-            let preferred = Self::PREFERRED_IMAGE_WIDTH_PX as i64;
+            let preferred = PREFERRED_IMAGE_WIDTH_PX.load(Ordering::Relaxed) as i64;
             let dist_to_accum = (preferred - accum.width.unwrap_or_default() as i64).abs();
             let dist_to_item = (preferred - item.width.unwrap_or_default() as i64).abs();
             if dist_to_accum <= dist_to_item {
@@ -61,8 +69,8 @@ impl SimplifiedItem {
         })
     }
 
-    fn get_image_url(playback: &CurrentPlaybackContext) -> Option<String> {
-        match &playback.item {
+    fn get_image_url(item: Option<&PlayableItem>) -> Option<String> {
+        match item {
             Some(PlayableItem::Track(FullTrack { album, .. })) => {
                 Self::get_preferred_image(&album.images).map(|image| image.url.clone())
             }
@@ -73,16 +81,16 @@ impl SimplifiedItem {
         }
     }
 
-    fn get_duration_ms(playback: &CurrentPlaybackContext) -> u64 {
-        match &playback.item {
+    fn get_duration_ms(item: Option<&PlayableItem>) -> u64 {
+        match item {
             Some(PlayableItem::Track(t)) => t.duration.as_millis() as u64,
             Some(PlayableItem::Episode(t)) => t.duration.as_millis() as u64,
             _ => Default::default(),
         }
     }
 
-    fn get_name(playback: &CurrentPlaybackContext) -> Option<String> {
-        match &playback.item {
+    fn get_name(item: Option<&PlayableItem>) -> Option<String> {
+        match item {
             Some(PlayableItem::Track(t)) => Some(t.name.clone()),
             Some(PlayableItem::Episode(t)) => Some(t.name.clone()),
             _ => None,
@@ -92,16 +100,32 @@ impl SimplifiedItem {
 
 impl From<CurrentPlaybackContext> for SimplifiedItem {
     fn from(playback: CurrentPlaybackContext) -> Self {
+        let item = playback.item.as_ref();
         Self {
-            id: Self::get_id(&playback),
-            name: Self::get_name(&playback),
-            image_url: Self::get_image_url(&playback),
-            artists: Self::get_artists(&playback),
-            album: Self::get_album(&playback),
-            duration_ms: Self::get_duration_ms(&playback),
+            id: Self::get_id(item),
+            name: Self::get_name(item),
+            image_url: Self::get_image_url(item),
+            artists: Self::get_artists(item),
+            album: Self::get_album(item),
+            duration_ms: Self::get_duration_ms(item),
             progress_ms: playback.progress.unwrap_or_default().as_millis() as u64,
             context_uri: playback.context.map(|c| c.uri),
             ..Self::default()
         }
     }
 }
+
+impl From<PlayableItem> for SimplifiedItem {
+    fn from(item: PlayableItem) -> Self {
+        let item = Some(&item);
+        Self {
+            id: Self::get_id(item),
+            name: Self::get_name(item),
+            image_url: Self::get_image_url(item),
+            artists: Self::get_artists(item),
+            album: Self::get_album(item),
+            duration_ms: Self::get_duration_ms(item),
+            ..Self::default()
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex as SyncMutex;
+use std::time::Duration;
+use tauri::Manager;
+use tauri_plugin_store::{with_store, StoreCollection};
+use tokio::sync::mpsc;
+
+use crate::paths;
+
+/// How long writes are held back for more changes to come in, e.g. while a
+/// window is being dragged, and how long at most while changes keep coming.
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(1);
+const MAX_SAVE_DELAY: Duration = Duration::from_secs(5);
+
+type Cache = HashMap<String, JsonValue>;
+
+/// The next version of the store, written in full before it replaces it.
+fn temp_path(store_path: &Path) -> PathBuf {
+    store_path.with_extension("bin.tmp")
+}
+
+/// The version of the store before the last write.
+fn backup_path(store_path: &Path) -> PathBuf {
+    store_path.with_extension("bin.bak")
+}
+
+fn is_readable(path: &Path) -> bool {
+    std::fs::read(path).map_or(false, |contents| {
+        serde_json::from_slice::<Cache>(&contents).is_ok()
+    })
+}
+
+/// Replaces the store so that a crash at any point leaves either the old or
+/// the new version on disk, never a mix of both.
+fn write_atomic(store_path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = store_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let temp_path = temp_path(store_path);
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    drop(file);
+
+    if is_readable(store_path) {
+        std::fs::copy(store_path, backup_path(store_path))?;
+    }
+    std::fs::rename(&temp_path, store_path)?;
+
+    #[cfg(unix)]
+    if let Some(dir) = store_path.parent() {
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Serializes writes, since they all go through the same temp file and
+/// each has to read the cache after the one before it.
+static WRITE_LOCK: SyncMutex<()> = SyncMutex::new(());
+
+/// Writes the store to disk right away. Used for changes that must survive
+/// a crash, such as a refreshed token.
+pub fn save_now(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+    // Held from reading the cache until it is on disk. Otherwise a write
+    // that read the cache earlier could land last and undo a newer one,
+    // such as a refreshed token.
+    let _guard = WRITE_LOCK.lock().unwrap();
+
+    let store_path = paths::store_path(app_handle);
+    let collection = app_handle.state::<StoreCollection>();
+    let contents = with_store(app_handle, collection, store_path.clone(), |store| {
+        Ok(serde_json::to_vec(&store.cache))
+    })
+    .map_err(|e| anyhow::anyhow!(e.to_string()))??;
+
+    write_atomic(&store_path, &contents)?;
+    Ok(())
+}
+
+pub struct StoreWriter(mpsc::UnboundedSender<()>);
+
+/// Starts the task that writes the store shortly after it changes.
+pub fn spawn_writer(app_handle: &tauri::AppHandle) -> StoreWriter {
+    let (dirty_tx, mut dirty_rx) = mpsc::unbounded_channel();
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        while dirty_rx.recv().await.is_some() {
+            let deadline = tokio::time::Instant::now() + MAX_SAVE_DELAY;
+            loop {
+                let now = tokio::time::Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                let wait = SAVE_DEBOUNCE.min(deadline - now);
+                match tokio::time::timeout(wait, dirty_rx.recv()).await {
+                    Ok(Some(())) => continue,
+                    _ => break,
+                }
+            }
+            if let Err(error) = save_now(&app_handle) {
+                tracing::error!(%error, "failed to save store");
+            }
+        }
+    });
+    StoreWriter(dirty_tx)
+}
+
+/// Writes the store soon, together with any other change made meanwhile.
+pub fn schedule_save(app_handle: &tauri::AppHandle) {
+    let scheduled = app_handle
+        .try_state::<StoreWriter>()
+        .map_or(false, |writer| writer.0.send(()).is_ok());
+    if !scheduled {
+        if let Err(error) = save_now(app_handle) {
+            tracing::error!(%error, "failed to save store");
+        }
+    }
+}
+
+/// Gets the store file into a state the store plugin can load. Has to run
+/// before the plugin loads it.
+///
+/// A crash can leave a finished temp file that never replaced the store, or
+/// an older release can have left a half written store. The most recent
+/// readable version wins, and an unreadable store is kept aside for
+/// inspection instead of being overwritten.
+pub fn recover(store_path: &Path) {
+    // The temp file is synced before the store is touched, so a readable one
+    // is always newer than the store next to it.
+    let temp_path = temp_path(store_path);
+    if is_readable(&temp_path) {
+        match std::fs::rename(&temp_path, store_path) {
+            Ok(()) => tracing::warn!("recovered store from an unfinished write"),
+            Err(error) => tracing::error!(%error, "failed to recover unfinished store write"),
+        }
+    } else if temp_path.exists() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+
+    if !store_path.exists() || is_readable(store_path) {
+        return;
+    }
+
+    let corrupt_path = store_path.with_extension(format!(
+        "corrupt-{}.bin",
+        chrono::Utc::now().timestamp_millis()
+    ));
+    if let Err(error) = std::fs::rename(store_path, &corrupt_path) {
+        tracing::error!(%error, "store is unreadable and could not be moved aside");
+        return;
+    }
+
+    let backup_path = backup_path(store_path);
+    if is_readable(&backup_path) && std::fs::copy(&backup_path, store_path).is_ok() {
+        tracing::warn!(
+            corrupt = %corrupt_path.display(),
+            "store is unreadable, moved it aside and restored the previous version"
+        );
+    } else {
+        tracing::warn!(
+            corrupt = %corrupt_path.display(),
+            "store is unreadable, moved it aside and starting from an empty one"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store_in(dir: &tempfile::TempDir) -> PathBuf {
+        dir.path().join("store.bin")
+    }
+
+    #[test]
+    fn writes_replace_the_store_and_keep_a_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = store_in(&dir);
+
+        write_atomic(&store_path, br#"{"token":1}"#).unwrap();
+        write_atomic(&store_path, br#"{"token":2}"#).unwrap();
+
+        assert_eq!(std::fs::read(&store_path).unwrap(), br#"{"token":2}"#);
+        assert_eq!(
+            std::fs::read(backup_path(&store_path)).unwrap(),
+            br#"{"token":1}"#
+        );
+        assert!(!temp_path(&store_path).exists());
+    }
+
+    #[test]
+    fn recovers_a_finished_write_that_crashed_before_the_rename() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = store_in(&dir);
+        write_atomic(&store_path, br#"{"token":1}"#).unwrap();
+
+        write_atomic(&store_path, br#"{"token":2}"#).unwrap();
+
+        // A crash between backing up the store and the rename, with the
+        // synced temp file holding the newest version.
+        std::fs::write(temp_path(&store_path), br#"{"token":3}"#).unwrap();
+        assert!(backup_path(&store_path).exists());
+        recover(&store_path);
+
+        assert_eq!(std::fs::read(&store_path).unwrap(), br#"{"token":3}"#);
+        assert!(!temp_path(&store_path).exists());
+    }
+
+    #[test]
+    fn drops_a_half_written_temp_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = store_in(&dir);
+        write_atomic(&store_path, br#"{"token":1}"#).unwrap();
+
+        std::fs::write(temp_path(&store_path), br#"{"tok"#).unwrap();
+        recover(&store_path);
+
+        assert_eq!(std::fs::read(&store_path).unwrap(), br#"{"token":1}"#);
+        assert!(!temp_path(&store_path).exists());
+    }
+
+    #[test]
+    fn moves_a_corrupt_store_aside_and_restores_the_backup() {
+        let dir = tempfile::tempdir().unwrap();
+        let store_path = store_in(&dir);
+        write_atomic(&store_path, br#"{"token":1}"#).unwrap();
+        write_atomic(&store_path, br#"{"token":2}"#).unwrap();
+
+        std::fs::write(&store_path, b"\0\0garbage").unwrap();
+        recover(&store_path);
+
+        assert_eq!(std::fs::read(&store_path).unwrap(), br#"{"token":1}"#);
+        let corrupt = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_name().to_string_lossy().contains("corrupt-"))
+            .count();
+        assert_eq!(corrupt, 1);
+    }
+}
@@ -0,0 +1,119 @@
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use tauri::Manager;
+use tauri_plugin_store::{with_store, StoreCollection};
+
+use crate::paths;
+use crate::store_file;
+
+pub const STORE_VERSION_KEY: &str = "schema_version";
+
+type Cache = HashMap<String, JsonValue>;
+
+/// `MIGRATIONS[n]` takes a store from version `n` to `n + 1`. Stores from
+/// before versioning count as version 0. Only ever append to this list.
+const MIGRATIONS: &[fn(&mut Cache)] = &[rename_access_token];
+
+pub const CURRENT_VERSION: u64 = MIGRATIONS.len() as u64;
+
+/// The token was stored as `access_token`, but the entry is the whole
+/// `Token`, refresh token included.
+fn rename_access_token(cache: &mut Cache) {
+    if let Some(token) = cache.remove("access_token") {
+        cache.insert("token".to_string(), token);
+    }
+}
+
+fn version(cache: &Cache) -> u64 {
+    cache
+        .get(STORE_VERSION_KEY)
+        .and_then(JsonValue::as_u64)
+        .unwrap_or(0)
+}
+
+/// Brings `cache` up to [`CURRENT_VERSION`]. Returns whether anything ran. A
+/// store written by a newer version of the app is left alone.
+fn migrate_cache(cache: &mut Cache) -> bool {
+    let from = version(cache);
+    if from > CURRENT_VERSION {
+        tracing::warn!(
+            version = from,
+            supported = CURRENT_VERSION,
+            "store is from a newer version"
+        );
+        return false;
+    }
+    if from == CURRENT_VERSION {
+        return false;
+    }
+
+    for (version, migration) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        tracing::info!(from = version, to = version + 1, "migrating store");
+        migration(cache);
+    }
+    cache.insert(STORE_VERSION_KEY.to_string(), CURRENT_VERSION.into());
+    true
+}
+
+/// Migrates the loaded store and saves it if anything changed.
+pub fn migrate(app_handle: &tauri::AppHandle) -> anyhow::Result<()> {
+    let collection = app_handle.state::<StoreCollection>();
+    let migrated = with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| Ok(migrate_cache(&mut store.cache)),
+    )
+    .map_err(|e| anyhow::anyhow!(e.to_string()))?;
+
+    if migrated {
+        store_file::save_now(app_handle)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn cache(entries: JsonValue) -> Cache {
+        serde_json::from_value(entries).unwrap()
+    }
+
+    #[test]
+    fn migrates_unversioned_stores() {
+        let mut store = cache(json!({
+            "access_token": { "access_token": "a", "refresh_token": "r" },
+            "settings": {},
+        }));
+        assert!(migrate_cache(&mut store));
+
+        assert!(!store.contains_key("access_token"));
+        assert_eq!(store["token"]["refresh_token"], "r");
+        assert_eq!(store["settings"], json!({}));
+        assert_eq!(version(&store), CURRENT_VERSION);
+    }
+
+    #[test]
+    fn leaves_current_stores_alone() {
+        let mut store = cache(json!({
+            "schema_version": CURRENT_VERSION,
+            "token": { "refresh_token": "r" },
+        }));
+        let before = store.clone();
+        assert!(!migrate_cache(&mut store));
+        assert_eq!(store, before);
+    }
+
+    #[test]
+    fn leaves_newer_stores_alone() {
+        let mut store = cache(json!({
+            "schema_version": CURRENT_VERSION + 1,
+            "access_token": "from a future release",
+        }));
+        let before = store.clone();
+        assert!(!migrate_cache(&mut store));
+        assert_eq!(store, before);
+    }
+}
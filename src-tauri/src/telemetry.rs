@@ -0,0 +1,79 @@
+use std::path::Path;
+use std::sync::Mutex as SyncMutex;
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+pub const LOG_FILE_NAME: &str = "spotify-mini-player.log";
+/// A week of daily files. Older ones are deleted as new ones are started.
+const MAX_LOG_FILES: usize = 7;
+
+/// Holds the file writer's guard so shutdown can flush the log before the
+/// process exits, which skips destructors.
+pub struct LogGuard(pub SyncMutex<Option<WorkerGuard>>);
+
+pub struct LogLevel {
+    directive: SyncMutex<String>,
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogLevel {
+    pub fn get(&self) -> String {
+        self.directive.lock().unwrap().clone()
+    }
+
+    /// Accepts anything `RUST_LOG` would, e.g. `debug` or `spotify_mini_player=trace`.
+    pub fn set(&self, directive: &str) -> Result<(), String> {
+        let filter = EnvFilter::try_new(directive).map_err(|e| e.to_string())?;
+        self.handle.reload(filter).map_err(|e| e.to_string())?;
+        *self.directive.lock().unwrap() = directive.into();
+        tracing::info!(directive, "log level changed");
+        Ok(())
+    }
+}
+
+/// Logs to stderr and to a daily rotated file in `log_dir`, or only to
+/// stderr if the file can't be created. The returned guard flushes the file
+/// writer when dropped, so keep it alive until exit.
+pub fn init(log_dir: &Path) -> (LogLevel, LogGuard) {
+    let directive = std::env::var("RUST_LOG").unwrap_or_else(|_| DEFAULT_LOG_LEVEL.into());
+    let filter =
+        EnvFilter::try_new(&directive).unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_LEVEL));
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let file_appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_FILE_NAME)
+        .max_log_files(MAX_LOG_FILES)
+        .build(log_dir);
+    let (file_layer, guard, file_error) = match file_appender {
+        Ok(file_appender) => {
+            let (file_writer, guard) = tracing_appender::non_blocking(file_appender);
+            let file_layer = tracing_subscriber::fmt::layer()
+                .json()
+                .with_span_events(FmtSpan::CLOSE)
+                .with_writer(file_writer);
+            (Some(file_layer), Some(guard), None)
+        }
+        Err(error) => (None, None, Some(error)),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE))
+        .with(file_layer)
+        .init();
+    if let Some(error) = file_error {
+        tracing::error!(%error, dir = %log_dir.display(), "failed to open log file");
+    }
+
+    let log_level = LogLevel {
+        directive: SyncMutex::new(directive),
+        handle,
+    };
+    (log_level, LogGuard(SyncMutex::new(guard)))
+}
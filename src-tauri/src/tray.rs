@@ -0,0 +1,223 @@
+use rspotify::model::RepeatState;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex as SyncMutex;
+use tauri::{
+    CustomMenuItem, Manager, SystemTray, SystemTrayMenu, SystemTrayMenuItem,
+    SystemTrayMenuItemHandle,
+};
+use tauri_plugin_store::{with_store, StoreCollection};
+
+use crate::handlers::control::{self, ControlCommand};
+use crate::paths;
+use crate::shutdown;
+use crate::state::{AppState, PlayableId, PlaybackEvent};
+use crate::window::{self, WindowMode, WindowSettings};
+
+const TRACK_ITEM: &str = "track";
+const PLAY_PAUSE_ITEM: &str = "play_pause";
+const NEXT_TRACK_ITEM: &str = "next_track";
+const PREV_TRACK_ITEM: &str = "prev_track";
+const TOGGLE_SAVED_ITEM: &str = "toggle_saved";
+const TOGGLE_SHUFFLE_ITEM: &str = "toggle_shuffle";
+const CYCLE_REPEAT_STATE_ITEM: &str = "cycle_repeat_state";
+const DETACHED_ITEM: &str = "detached";
+const QUIT_ITEM: &str = "quit";
+/// Linux trays don't report clicks on the icon itself, so the popup is
+/// opened from the menu there.
+pub const SHOW_PLAYER_ITEM: &str = "show_player";
+
+pub const STORE_TRAY_TITLE_KEY: &str = "tray_title";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TrayTitleOptions {
+    pub enabled: bool,
+    pub show_artists: bool,
+    pub max_chars: usize,
+}
+
+impl Default for TrayTitleOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            show_artists: true,
+            max_chars: 32,
+        }
+    }
+}
+
+pub struct TrayTitle(pub SyncMutex<TrayTitleOptions>);
+
+pub fn build() -> SystemTray {
+    let menu = SystemTrayMenu::new();
+    #[cfg(target_os = "linux")]
+    let menu = menu
+        .add_item(CustomMenuItem::new(SHOW_PLAYER_ITEM, "Show Player"))
+        .add_native_item(SystemTrayMenuItem::Separator);
+    let menu = menu
+        .add_item(CustomMenuItem::new(TRACK_ITEM, "Not playing").disabled())
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(PLAY_PAUSE_ITEM, "Play"))
+        .add_item(CustomMenuItem::new(NEXT_TRACK_ITEM, "Next"))
+        .add_item(CustomMenuItem::new(PREV_TRACK_ITEM, "Previous"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(TOGGLE_SAVED_ITEM, "Like"))
+        .add_item(CustomMenuItem::new(TOGGLE_SHUFFLE_ITEM, "Shuffle"))
+        .add_item(CustomMenuItem::new(CYCLE_REPEAT_STATE_ITEM, "Repeat: Off"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(DETACHED_ITEM, "Detached Window"))
+        .add_native_item(SystemTrayMenuItem::Separator)
+        .add_item(CustomMenuItem::new(QUIT_ITEM, "Quit"));
+
+    SystemTray::new().with_menu(menu)
+}
+
+fn now_playing(state: &AppState, show_artists: bool) -> Option<String> {
+    let curr = state.curr.as_ref()?;
+    let name = curr.name.as_deref().unwrap_or("Unknown");
+    let artists: Vec<&str> = curr.artists.iter().map(|a| a.name.as_str()).collect();
+    match artists.is_empty() || !show_artists {
+        true => Some(name.into()),
+        false => Some(format!("{} — {}", name, artists.join(", "))),
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        return text.into();
+    }
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+fn repeat_title(repeat_state: RepeatState) -> &'static str {
+    match repeat_state {
+        RepeatState::Off => "Repeat: Off",
+        RepeatState::Context => "Repeat: All",
+        RepeatState::Track => "Repeat: One",
+    }
+}
+
+fn item(app_handle: &tauri::AppHandle, id: &str) -> SystemTrayMenuItemHandle {
+    app_handle.tray_handle().get_item(id)
+}
+
+/// Keeps the menu labels in sync with the state. Progress ticks are ignored
+/// since nothing in the menu depends on them.
+fn update_menu(app_handle: &tauri::AppHandle, state: &AppState, events: &[PlaybackEvent]) {
+    if events
+        .iter()
+        .all(|event| matches!(event, PlaybackEvent::ProgressChanged(_)))
+    {
+        return;
+    }
+    set_menu(app_handle, state);
+}
+
+fn set_menu(app_handle: &tauri::AppHandle, state: &AppState) {
+    let is_track = matches!(
+        state.curr.as_ref().and_then(|c| c.id.as_ref()),
+        Some(PlayableId::Track(_))
+    );
+    let saved = state.curr.as_ref().map_or(false, |c| c.saved);
+    let play_pause_title = if state.playing { "Pause" } else { "Play" };
+    let track_title = now_playing(state, true).unwrap_or_else(|| "Not playing".into());
+
+    let _ = item(app_handle, TRACK_ITEM).set_title(track_title);
+    let _ = item(app_handle, PLAY_PAUSE_ITEM).set_title(play_pause_title);
+    let _ = item(app_handle, TOGGLE_SAVED_ITEM).set_enabled(is_track);
+    let _ = item(app_handle, TOGGLE_SAVED_ITEM).set_selected(saved);
+    let _ = item(app_handle, TOGGLE_SHUFFLE_ITEM).set_selected(state.shuffle);
+    let _ = item(app_handle, CYCLE_REPEAT_STATE_ITEM).set_title(repeat_title(state.repeat_state));
+}
+
+/// Shows the current track next to the tray icon on macOS and Linux, and as
+/// the icon's tooltip on Windows, which has no tray titles. Linux has no tray
+/// tooltips instead.
+pub fn update_title(app_handle: &tauri::AppHandle, state: &AppState) {
+    let options = app_handle.state::<TrayTitle>().0.lock().unwrap().clone();
+    let text = now_playing(state, options.show_artists)
+        .filter(|_| options.enabled)
+        .map(|text| truncate(&text, options.max_chars));
+
+    #[cfg(not(target_os = "windows"))]
+    let _ = app_handle
+        .tray_handle()
+        .set_title(text.as_deref().unwrap_or_default());
+    #[cfg(target_os = "windows")]
+    let _ = app_handle
+        .tray_handle()
+        .set_tooltip(text.as_deref().unwrap_or("spotify mini player"));
+}
+
+pub fn update_window_mode(app_handle: &tauri::AppHandle, mode: WindowMode) {
+    let _ = item(app_handle, DETACHED_ITEM).set_selected(mode == WindowMode::Detached);
+}
+
+/// Fills the menu and title from the playback restored from the last run,
+/// which no event announces.
+pub fn show_restored(app_handle: &tauri::AppHandle, state: &AppState) {
+    set_menu(app_handle, state);
+    update_title(app_handle, state);
+}
+
+pub fn on_state_change(app_handle: &tauri::AppHandle, state: &AppState, events: &[PlaybackEvent]) {
+    update_menu(app_handle, state, events);
+    if events
+        .iter()
+        .any(|event| matches!(event, PlaybackEvent::TrackChanged(_)))
+    {
+        update_title(app_handle, state);
+    }
+}
+
+pub fn load_title_options(app_handle: &tauri::AppHandle) -> TrayTitleOptions {
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| Ok(store.cache.get(STORE_TRAY_TITLE_KEY).cloned()),
+    )
+    .ok()
+    .flatten()
+    .and_then(|value| serde_json::from_value(value).ok())
+    .unwrap_or_default()
+}
+
+async fn dispatch(app_handle: &tauri::AppHandle, id: &str) {
+    let command = match id {
+        PLAY_PAUSE_ITEM => ControlCommand::PlayPause,
+        NEXT_TRACK_ITEM => ControlCommand::NextTrack,
+        PREV_TRACK_ITEM => ControlCommand::PrevTrack,
+        TOGGLE_SAVED_ITEM => ControlCommand::ToggleSaved,
+        TOGGLE_SHUFFLE_ITEM => ControlCommand::ToggleShuffle,
+        CYCLE_REPEAT_STATE_ITEM => ControlCommand::CycleRepeatState,
+        _ => return,
+    };
+    let _ = control::execute(app_handle, command).await;
+}
+
+pub fn on_menu_item_click(app_handle: &tauri::AppHandle, id: String) {
+    if id == QUIT_ITEM {
+        tauri::async_runtime::spawn(shutdown::quit(app_handle.clone()));
+        return;
+    }
+
+    if id == DETACHED_ITEM {
+        let mode = match app_handle.state::<WindowSettings>().0.lock().unwrap().mode {
+            WindowMode::Popover => WindowMode::Detached,
+            WindowMode::Detached => WindowMode::Popover,
+        };
+        if let Err(error) = window::set_mode(app_handle, mode) {
+            tracing::warn!(%error, "failed to change window mode");
+        }
+        return;
+    }
+
+    let app_handle = app_handle.clone();
+    tauri::async_runtime::spawn(async move {
+        dispatch(&app_handle, &id).await;
+    });
+}
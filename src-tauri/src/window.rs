@@ -0,0 +1,345 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex as SyncMutex;
+use tauri::{
+    AppHandle, GlobalWindowEvent, LogicalSize, Manager, Monitor, PhysicalPosition, PhysicalSize,
+    Window, WindowBuilder, WindowEvent,
+};
+use tauri_plugin_positioner::WindowExt;
+use tauri_plugin_store::{with_store, StoreCollection};
+
+use crate::handlers::subscribe_to_event_loop;
+use crate::paths;
+use crate::platform;
+use crate::reauth::reauth_spotify;
+use crate::settings::{self, Settings};
+use crate::state::AppStore;
+use crate::store_file;
+use crate::tray;
+
+pub const MAIN_WINDOW: &str = "main";
+pub const STORE_WINDOW_KEY: &str = "window";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    /// Anchored to the tray icon and closed as soon as it loses focus.
+    #[default]
+    Popover,
+    /// A floating window that stays open until closed from the tray.
+    Detached,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowLayout {
+    /// A single bar with the controls.
+    Compact,
+    /// The cover art card.
+    #[default]
+    Standard,
+    /// The card plus the queue and the device picker.
+    Expanded,
+}
+
+/// Where the detached window was last left on a monitor, in physical pixels.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct WindowPlacement {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// The size only applies while the window still uses this layout.
+    #[serde(default)]
+    pub layout: WindowLayout,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WindowOptions {
+    pub mode: WindowMode,
+    pub layout: WindowLayout,
+    /// Keyed by monitor name, so the window comes back where it was when
+    /// the same monitor is connected again.
+    pub placements: HashMap<String, WindowPlacement>,
+    pub last_monitor: Option<String>,
+}
+
+pub struct WindowSettings(pub SyncMutex<WindowOptions>);
+
+pub fn load_options(app_handle: &AppHandle) -> WindowOptions {
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| Ok(store.cache.get(STORE_WINDOW_KEY).cloned()),
+    )
+    .ok()
+    .flatten()
+    .and_then(|value| serde_json::from_value(value).ok())
+    .unwrap_or_default()
+}
+
+fn save_options(app_handle: &AppHandle, options: &WindowOptions) -> Result<(), String> {
+    let serialized_options = serde_json::to_value(options).map_err(|e| e.to_string())?;
+    let collection = app_handle.state::<StoreCollection>();
+    with_store(
+        app_handle,
+        collection,
+        paths::store_path(app_handle),
+        |store| {
+            Ok(store
+                .cache
+                .insert(STORE_WINDOW_KEY.to_string(), serialized_options))
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    store_file::schedule_save(app_handle);
+    Ok(())
+}
+
+fn mode(app_handle: &AppHandle) -> WindowMode {
+    app_handle.state::<WindowSettings>().0.lock().unwrap().mode
+}
+
+fn layout(app_handle: &AppHandle) -> WindowLayout {
+    app_handle
+        .state::<WindowSettings>()
+        .0
+        .lock()
+        .unwrap()
+        .layout
+}
+
+fn size(app_handle: &AppHandle, layout: WindowLayout) -> LogicalSize<f64> {
+    settings::get(app_handle).window_size(layout)
+}
+
+fn monitor_key(monitor: &Monitor) -> String {
+    match monitor.name() {
+        Some(name) => name.clone(),
+        None => format!("{}x{}", monitor.size().width, monitor.size().height),
+    }
+}
+
+/// Puts the window where it was last left, preferring the monitor it was on
+/// most recently. Windows without a remembered placement are centered.
+fn restore_placement(window: &Window) -> tauri::Result<()> {
+    let app_handle = window.app_handle();
+    let options = app_handle
+        .state::<WindowSettings>()
+        .0
+        .lock()
+        .unwrap()
+        .clone();
+    let connected: Vec<String> = window
+        .available_monitors()?
+        .iter()
+        .map(monitor_key)
+        .collect();
+
+    let placement = options
+        .last_monitor
+        .iter()
+        .chain(connected.iter())
+        .filter(|key| connected.contains(*key))
+        .find_map(|key| options.placements.get(key));
+
+    match placement {
+        Some(placement) => {
+            match placement.layout == options.layout {
+                true => window.set_size(PhysicalSize::new(placement.width, placement.height))?,
+                false => window.set_size(size(&app_handle, options.layout))?,
+            }
+            window.set_position(PhysicalPosition::new(placement.x, placement.y))
+        }
+        None => {
+            window.set_size(size(&app_handle, options.layout))?;
+            window.center()
+        }
+    }
+}
+
+fn remember_placement(window: &Window) -> tauri::Result<()> {
+    let monitor = match window.current_monitor()? {
+        Some(monitor) => monitor_key(&monitor),
+        None => return Ok(()),
+    };
+    let position = window.outer_position()?;
+    let size = window.inner_size()?;
+
+    let app_handle = window.app_handle();
+    let settings = app_handle.state::<WindowSettings>();
+    let mut options = settings.0.lock().unwrap();
+    options.placements.insert(
+        monitor.clone(),
+        WindowPlacement {
+            x: position.x,
+            y: position.y,
+            width: size.width,
+            height: size.height,
+            layout: options.layout,
+        },
+    );
+    options.last_monitor = Some(monitor);
+    if let Err(error) = save_options(&app_handle, &options) {
+        tracing::warn!(%error, "failed to save window placement");
+    }
+    Ok(())
+}
+
+fn apply_mode(window: &Window, mode: WindowMode) -> tauri::Result<()> {
+    match mode {
+        WindowMode::Popover => {
+            let app_handle = window.app_handle();
+            window.set_resizable(false)?;
+            window.set_size(size(&app_handle, layout(&app_handle)))?;
+            window.move_window(platform::WINDOW_POSITION)
+        }
+        WindowMode::Detached => {
+            window.set_resizable(true)?;
+            restore_placement(window)
+        }
+    }
+}
+
+fn create_window(app_handle: &AppHandle) -> tauri::Result<Window> {
+    let layout_size = size(app_handle, layout(app_handle));
+    let builder = WindowBuilder::new(
+        app_handle,
+        MAIN_WINDOW,
+        tauri::WindowUrl::App("index.html".into()),
+    )
+    .fullscreen(false)
+    .inner_size(layout_size.width, layout_size.height)
+    .resizable(false)
+    .title("spotify mini player")
+    .visible(false)
+    .always_on_top(true);
+    let window = platform::window_builder(builder).build()?;
+    apply_mode(&window, mode(app_handle))?;
+    platform::style_window(&window)?;
+
+    window.show()?;
+    Ok(window)
+}
+
+/// Opens the player from the tray, or closes it again if it is open, in
+/// either mode. A detached window comes back where it was left.
+pub fn toggle_window(app_handle: &AppHandle) {
+    match app_handle.get_window(MAIN_WINDOW) {
+        Some(window) => {
+            if let Ok(true) = window.is_visible() {
+                if let Err(error) = window.close() {
+                    tracing::error!(%error, "failed to close window");
+                }
+            }
+        }
+        None => {
+            if let Err(error) = create_window(app_handle) {
+                tracing::error!(%error, "failed to create window");
+                return;
+            }
+            if let Err(error) = subscribe_to_event_loop(app_handle) {
+                tracing::error!(%error, "failed to subscribe to the event loop");
+            }
+            let app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                // Fails without a token, e.g. on a fresh install or after a
+                // corrupt store was moved aside, until the user logs in.
+                if let Err(error) = reauth_spotify(&app_handle).await {
+                    tracing::warn!(%error, "failed to refresh the spotify token");
+                    return;
+                }
+                if let Err(error) = app_handle.state::<AppStore>().get_current_playback().await {
+                    tracing::warn!(%error, "failed to fetch playback");
+                }
+            });
+        }
+    }
+}
+
+/// Opens the player, or brings it to the front if it is already open.
+pub fn show_window(app_handle: &AppHandle) {
+    match app_handle.get_window(MAIN_WINDOW) {
+        Some(window) => {
+            let _ = window.set_focus();
+        }
+        None => toggle_window(app_handle),
+    }
+}
+
+/// Switches between the tray popover and the detached window, reshaping the
+/// open window in place if there is one.
+pub fn set_mode(app_handle: &AppHandle, mode: WindowMode) -> Result<(), String> {
+    {
+        let settings = app_handle.state::<WindowSettings>();
+        let mut options = settings.0.lock().unwrap();
+        options.mode = mode;
+        save_options(app_handle, &options)?;
+    }
+
+    if let Some(window) = app_handle.get_window(MAIN_WINDOW) {
+        apply_mode(&window, mode).map_err(|e| e.to_string())?;
+    }
+    tray::update_window_mode(app_handle, mode);
+    let _ = app_handle.emit_all("window_mode_changed", mode);
+    Ok(())
+}
+
+/// Resizes the window to the layout's preset. The popover is anchored to
+/// the tray again, while a detached window keeps its position.
+pub fn set_layout(app_handle: &AppHandle, layout: WindowLayout) -> Result<(), String> {
+    {
+        let settings = app_handle.state::<WindowSettings>();
+        let mut options = settings.0.lock().unwrap();
+        options.layout = layout;
+        save_options(app_handle, &options)?;
+    }
+
+    if let Some(window) = app_handle.get_window(MAIN_WINDOW) {
+        resize(&window, size(app_handle, layout)).map_err(|e| e.to_string())?;
+    }
+    let _ = app_handle.emit_all("window_layout_changed", layout);
+    Ok(())
+}
+
+fn resize(window: &Window, size: LogicalSize<f64>) -> tauri::Result<()> {
+    window.set_size(size)?;
+    if mode(&window.app_handle()) == WindowMode::Popover {
+        window.move_window(platform::WINDOW_POSITION)?;
+    }
+    Ok(())
+}
+
+/// Picks up changed layout sizes in the open window.
+pub fn apply_settings(app_handle: &AppHandle, settings: &Settings) -> tauri::Result<()> {
+    match app_handle.get_window(MAIN_WINDOW) {
+        Some(window) => resize(&window, settings.window_size(layout(app_handle))),
+        None => Ok(()),
+    }
+}
+
+pub fn on_window_event(event: GlobalWindowEvent) {
+    use WindowEvent::*;
+    let window = event.window();
+    if window.label() != MAIN_WINDOW {
+        return;
+    }
+
+    match (event.event(), mode(&window.app_handle())) {
+        (Focused(false), WindowMode::Popover) => {
+            #[cfg(not(debug_assertions))]
+            {
+                window.close().unwrap();
+            }
+        }
+        (Moved(_) | Resized(_), WindowMode::Detached) => {
+            if let Err(error) = remember_placement(window) {
+                tracing::warn!(%error, "failed to read window placement");
+            }
+        }
+        _ => {}
+    }
+}
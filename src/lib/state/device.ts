@@ -0,0 +1,9 @@
+export interface Device {
+  id: string | undefined;
+  is_active: boolean;
+  is_private_session: boolean;
+  is_restricted: boolean;
+  name: string;
+  type: string;
+  volume_percent: number | undefined;
+}
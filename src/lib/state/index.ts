@@ -1,6 +1,12 @@
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { writable, type Readable, get, type Subscriber } from "svelte/store";
 import type { SimplifiedItem } from "./simplified-item";
+import type { PlaybackError } from "./playback-error";
+import type {
+  ShortcutBindings,
+  ShortcutConflict,
+  ShortcutState,
+} from "./shortcuts";
 import { invoke } from "@tauri-apps/api";
 
 const REPEAT_STATE = {
@@ -16,6 +22,11 @@ interface AppState {
   repeatState: RepeatState;
   progressMs: number;
   shuffle: boolean;
+  stale: boolean;
+  coverPath?: string;
+  error?: PlaybackError;
+  shortcutBindings: ShortcutBindings;
+  shortcutConflicts: ShortcutConflict[];
 }
 
 const defaultAppStore: AppState = {
@@ -24,6 +35,29 @@ const defaultAppStore: AppState = {
   repeatState: "off",
   progressMs: 0,
   shuffle: false,
+  stale: false,
+  coverPath: undefined,
+  error: undefined,
+  shortcutBindings: {},
+  shortcutConflicts: [],
+};
+
+const ERROR_DISPLAY_MS = 5000;
+
+interface PlaybackEvents {
+  track_changed: SimplifiedItem | undefined;
+  playback_paused: number;
+  playback_resumed: number;
+  progress_changed: number;
+  shuffle_changed: boolean;
+  repeat_changed: RepeatState;
+  device_changed: string | undefined;
+  saved_changed: boolean;
+  stale_changed: boolean;
+}
+
+type PlaybackListeners = {
+  [K in keyof PlaybackEvents]: (payload: PlaybackEvents[K]) => void;
 };
 
 type Invalidator<T> = (value?: T) => void;
@@ -43,13 +77,47 @@ export const appStore = (() => {
     setStore(newState);
   };
 
-  const handlePlaybackPayload = (appState: AppState) => {
-    set(appState);
+  const setCurr = (v: Partial<SimplifiedItem>) => {
+    const curr = get(store).curr;
+    if (curr) set({ curr: Object.assign(curr, v) });
+  };
+
+  const playbackListeners: PlaybackListeners = {
+    track_changed: (curr) => set({ curr, progressMs: curr?.progressMs ?? 0 }),
+    playback_paused: (progressMs) => set({ playing: false, progressMs }),
+    playback_resumed: (progressMs) => set({ playing: true, progressMs }),
+    progress_changed: (progressMs) => {
+      setCurr({ progressMs });
+      set({ progressMs });
+    },
+    shuffle_changed: (shuffle) => set({ shuffle }),
+    repeat_changed: (repeatState) => set({ repeatState }),
+    device_changed: () => {},
+    saved_changed: (saved) => setCurr({ saved }),
+    stale_changed: (stale) =>
+      set({ stale, coverPath: stale ? get(store).coverPath : undefined }),
+  };
+
+  const initOnPlaybackError = (): Promise<UnlistenFn> => {
+    return listen<PlaybackError>("playback_error", (e) => {
+      set({ error: e.payload });
+      setTimeout(() => {
+        if (get(store).error === e.payload) set({ error: undefined });
+      }, ERROR_DISPLAY_MS);
+    });
+  };
+
+  const initOnShortcutConflicts = (): Promise<UnlistenFn> => {
+    return listen<ShortcutConflict[]>("shortcut_conflicts", (e) => {
+      set({ shortcutConflicts: e.payload });
+    });
   };
 
-  const initOnPlayback = (): Promise<UnlistenFn> => {
-    return listen<AppState>("app_state_change", (e) => {
-      handlePlaybackPayload(e.payload);
+  const initOnPlaybackEvent = <K extends keyof PlaybackEvents>(
+    event: K
+  ): Promise<UnlistenFn> => {
+    return listen<PlaybackEvents[K]>(event, (e) => {
+      playbackListeners[event](e.payload);
     });
   };
 
@@ -66,7 +134,21 @@ export const appStore = (() => {
       };
     },
     init: async () => {
-      listenerUnsubscribeList.set("app_state_change", await initOnPlayback());
+      for (const event of Object.keys(playbackListeners)) {
+        const key = event as keyof PlaybackEvents;
+        listenerUnsubscribeList.set(key, await initOnPlaybackEvent(key));
+      }
+      listenerUnsubscribeList.set("playback_error", await initOnPlaybackError());
+      listenerUnsubscribeList.set(
+        "shortcut_conflicts",
+        await initOnShortcutConflicts()
+      );
+      set(await invoke<AppState>("get_app_state"));
+      const shortcuts = await invoke<ShortcutState>("get_shortcuts");
+      set({
+        shortcutBindings: shortcuts.bindings,
+        shortcutConflicts: shortcuts.conflicts,
+      });
     },
     playPause: async () => {
       await invoke("play_pause");
@@ -86,5 +168,15 @@ export const appStore = (() => {
     cycleRepeatState: async () => {
       await invoke("cycle_repeat_state");
     },
+    seek: async (positionMs: number) => {
+      await invoke("seek", { positionMs });
+    },
+    setShortcuts: async (bindings: ShortcutBindings) => {
+      const shortcutConflicts = await invoke<ShortcutConflict[]>(
+        "set_shortcuts",
+        { bindings }
+      );
+      set({ shortcutBindings: bindings, shortcutConflicts });
+    },
   };
 })();
@@ -0,0 +1,20 @@
+import { invoke } from "@tauri-apps/api";
+import { listen } from "@tauri-apps/api/event";
+import { writable } from "svelte/store";
+
+export type WindowLayout = "compact" | "standard" | "expanded";
+
+export const layoutStore = (() => {
+  const { subscribe, set } = writable<WindowLayout>("standard");
+
+  return {
+    subscribe,
+    init: async () => {
+      await listen<WindowLayout>("window_layout_changed", (e) => set(e.payload));
+      set(await invoke<WindowLayout>("get_window_layout"));
+    },
+    setLayout: async (layout: WindowLayout) => {
+      await invoke("set_window_layout", { layout });
+    },
+  };
+})();
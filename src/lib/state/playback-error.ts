@@ -0,0 +1,15 @@
+export interface PlaybackError {
+  source: string;
+  severity: "warning" | "error";
+  kind:
+    | "auth"
+    | "forbidden"
+    | "no_active_device"
+    | "rate_limited"
+    | "spotify_unavailable"
+    | "network"
+    | "spotify"
+    | "internal";
+  message: string;
+  timestampMs: number;
+}
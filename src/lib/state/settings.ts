@@ -0,0 +1,42 @@
+import { invoke } from "@tauri-apps/api";
+import { listen } from "@tauri-apps/api/event";
+import { writable } from "svelte/store";
+
+export interface Size {
+  width: number;
+  height: number;
+}
+
+export interface Settings {
+  playbackPollMs: number;
+  backgroundPlaybackPollMs: number;
+  preferredImageWidthPx: number;
+  redirectUriPort: number;
+  windowSizes: {
+    compact: Size;
+    standard: Size;
+    expanded: Size;
+  };
+  httpApi: {
+    enabled: boolean;
+    port: number;
+    bindAddress: string;
+    allowedOrigins: string[];
+  };
+}
+
+export const settingsStore = (() => {
+  const { subscribe, set } = writable<Settings | null>(null);
+
+  return {
+    subscribe,
+    init: async () => {
+      await listen<Settings>("settings_changed", (e) => set(e.payload));
+      set(await invoke<Settings>("get_settings"));
+    },
+    updateSettings: async (settings: Settings) => {
+      await invoke("update_settings", { settings });
+    },
+    getHttpApiToken: () => invoke<string>("get_http_api_token"),
+  };
+})();
@@ -0,0 +1,29 @@
+export type ShortcutAction =
+  | "play_pause"
+  | "next_track"
+  | "prev_track"
+  | "toggle_saved"
+  | "seek_forward"
+  | "seek_backward";
+
+export const SHORTCUT_LABELS: Record<ShortcutAction, string> = {
+  play_pause: "Play / pause",
+  next_track: "Next track",
+  prev_track: "Previous track",
+  toggle_saved: "Like",
+  seek_forward: "Seek forward",
+  seek_backward: "Seek backward",
+};
+
+export type ShortcutBindings = Partial<Record<ShortcutAction, string>>;
+
+export interface ShortcutConflict {
+  action: ShortcutAction;
+  accelerator: string;
+  reason: string;
+}
+
+export interface ShortcutState {
+  bindings: ShortcutBindings;
+  conflicts: ShortcutConflict[];
+}